@@ -1,7 +0,0 @@
-pub mod column;
-pub mod entry;
-pub mod log;
-pub mod memtable;
-pub mod sstable;
-pub mod tree;
-pub mod wal;
@@ -1,16 +1,29 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::fs;
+use std::{
+    collections::HashMap,
+    fs,
+    sync::{Arc, Mutex},
+};
 
-use lsm_tree::{
+use copper::lsm_tree::{
+    self,
     column::Column,
-    tree::{self, LsmTree, Value},
+    db::Db,
+    merge::AddInt,
+    options::Options,
+    tree::{LsmTree, Value},
 };
 use prettytable::{Cell, Row, Table};
 use serde::{Deserialize, Serialize};
+use tauri::State;
 
-mod lsm_tree;
+/// Tables of the shops opened so far, shared by every command instead of reopening them on each request.
+#[derive(Default)]
+struct Shops {
+    tables: Mutex<HashMap<String, Db>>,
+}
 
 // Learn more about Tauri commands at https://tauri.app/v1/guides/features/command
 #[tauri::command]
@@ -31,26 +44,55 @@ fn get_shops() -> Vec<String> {
 }
 
 #[tauri::command]
-fn delete_shop(name: String) {
+fn delete_shop(shops: State<'_, Shops>, name: String) {
+    // Close the table before removing its files, a command still using it keeps the shop
+    let mut tables = shops.tables.lock().unwrap();
+    if let Some(db) = tables.remove(&name) {
+        if let Err(db) = db.close() {
+            tables.insert(name, db);
+            return;
+        }
+    }
     let _ = std::fs::remove_dir_all(format!("shops/{}", name));
 }
 
-#[tauri::command]
-fn create_shop(name: String) {
-    let columns = vec![
+/// Columns of every shop table.
+fn shop_columns() -> Vec<Column> {
+    vec![
         Column::new("Name", lsm_tree::column::DataType::Text),
         Column::new("Author", lsm_tree::column::DataType::Text),
         Column::new("Year", lsm_tree::column::DataType::Int),
         Column::new("Quantity", lsm_tree::column::DataType::Int),
-    ];
+    ]
+}
+
+/// Options of every shop table, the stock of a book is added to with `Db::merge`.
+/// The memtables of the shops hold 128 bytes, as they always did.
+fn shop_options() -> Options {
+    Options { merge_operator: Some(Arc::new(AddInt::new("Quantity"))), memtable_size: 128, ..Default::default() }
+}
+
+/// Returns the table of an existing shop, opening it on first use.
+fn open_shop(shops: &Shops, shop: &str) -> Db {
+    let mut tables = shops.tables.lock().unwrap();
+    tables
+        .entry(shop.to_string())
+        .or_insert_with(|| Db::open(format!("shops/{}", shop), shop_columns(), shop_options()).unwrap())
+        .clone()
+}
+
+#[tauri::command]
+fn create_shop(shops: State<'_, Shops>, name: String) {
     let shop_path = format!("shops/{}", name);
 
-    let _ = tree::LsmTree::new(shop_path, columns);
+    if let Ok(db) = Db::create(shop_path, shop_columns(), shop_options()) {
+        shops.tables.lock().unwrap().insert(name, db);
+    }
 }
 
 #[tauri::command]
-fn new_book(shop: String, name: String, author: String, year: String, quantity: String) {
-    let mut lsm_tree = LsmTree::load(format!("shops/{}", shop)).unwrap();
+fn new_book(shops: State<'_, Shops>, shop: String, name: String, author: String, year: String, quantity: String) {
+    let lsm_tree = open_shop(&shops, &shop);
     let key = name.as_bytes();
     let values = vec![
         name.as_bytes().to_vec(),
@@ -61,47 +103,45 @@ fn new_book(shop: String, name: String, author: String, year: String, quantity:
     let _ = lsm_tree.insert(&key, &values);
 }
 
-#[tauri::command]
-fn add_book(shop: String, name: String) {
-    let mut lsm_tree = LsmTree::load(format!("shops/{}", shop)).unwrap();
-    let data = lsm_tree.get(&name.as_bytes().to_vec()).unwrap();
-    let mut data = lsm_tree.decode(&data.unwrap());
-    let a = data.get("Quantity");
-    if let Value::Int(b) = a.unwrap() {
-        data.insert("Quantity".to_string(), Value::Int(b + 1));
+/// Changes the quantity of a book in a transaction, retried until no other command wrote the book meanwhile.
+fn update_quantity(db: &Db, name: &str, update: impl Fn(i32) -> i32) {
+    loop {
+        let mut transaction = db.begin_transaction();
+        let Some(data) = transaction.get(name.as_bytes()).unwrap() else {
+            return;
+        };
+        let data = db.decode(&data).unwrap();
         let values = vec![
             name.as_bytes().to_vec(),
             data.get("Author").unwrap().get_text().as_bytes().to_vec(),
             data.get("Year").unwrap().get_int().to_ne_bytes().to_vec(),
-            data.get("Quantity").unwrap().get_int().to_ne_bytes().to_vec(),
+            update(data.get("Quantity").unwrap().get_int()).to_ne_bytes().to_vec(),
         ];
+        transaction.put(name.as_bytes(), &values).unwrap();
 
-        let _ = lsm_tree.insert(&data.get("Name").unwrap().get_text().as_bytes().to_vec(), &values);
+        match db.commit(transaction) {
+            Err(err) if err.is_retryable() => continue,
+            _ => return,
+        }
     }
 }
 
 #[tauri::command]
-fn sell_book(shop: String, name: String) {
-    let mut lsm_tree = LsmTree::load(format!("shops/{}", shop)).unwrap();
-    let data = lsm_tree.get(&name.as_bytes().to_vec()).unwrap();
-    let mut data = lsm_tree.decode(&data.unwrap());
-    let a = data.get("Quantity");
-    if let Value::Int(b) = a.unwrap() {
-        data.insert("Quantity".to_string(), Value::Int(if b - 1 < 0 { 0 } else { b - 1 }));
-        let values = vec![
-            name.as_bytes().to_vec(),
-            data.get("Author").unwrap().get_text().as_bytes().to_vec(),
-            data.get("Year").unwrap().get_int().to_ne_bytes().to_vec(),
-            data.get("Quantity").unwrap().get_int().to_ne_bytes().to_vec(),
-        ];
+fn add_book(shops: State<'_, Shops>, shop: String, name: String) {
+    let db = open_shop(&shops, &shop);
+    // No need to read the book, the quantity is added to when it is next read
+    let _ = db.merge(name.as_bytes(), &1_i32.to_ne_bytes());
+}
 
-        let _ = lsm_tree.insert(&data.get("Name").unwrap().get_text().as_bytes().to_vec(), &values);
-    }
+#[tauri::command]
+fn sell_book(shops: State<'_, Shops>, shop: String, name: String) {
+    let db = open_shop(&shops, &shop);
+    update_quantity(&db, &name, |quantity| if quantity - 1 < 0 { 0 } else { quantity - 1 });
 }
 
 #[tauri::command]
-fn remove_book(shop: String, name: String) {
-    let mut lsm_tree = LsmTree::load(format!("shops/{}", shop)).unwrap();
+fn remove_book(shops: State<'_, Shops>, shop: String, name: String) {
+    let lsm_tree = open_shop(&shops, &shop);
     let _ = lsm_tree.delete(&name.as_bytes().to_vec());
 }
 
@@ -113,48 +153,93 @@ pub struct Book {
     pub quantity: String,
 }
 
+/// Serializes a book row as a JSON `Book`.
+fn book_json(lsm_tree: &LsmTree, row: &[u8]) -> String {
+    let decode = lsm_tree.decode(row).unwrap();
+    let name = decode.get("Name").unwrap().get_text();
+    let author = decode.get("Author").unwrap().get_text();
+    let year = decode.get("Year").unwrap().get_int();
+    let in_stock = decode.get("Quantity").unwrap().get_int();
+
+    let book = Book {
+        name,
+        author,
+        year: year.to_string(),
+        quantity: in_stock.to_string(),
+    };
+
+    serde_json::to_string(&book).unwrap()
+}
+
 #[tauri::command]
-fn fetch_book(shop: String) -> Vec<String> {
-    let lsm_tree = LsmTree::load(format!("shops/{}", shop)).unwrap();
+fn fetch_book(shops: State<'_, Shops>, shop: String) -> Vec<String> {
+    let db = open_shop(&shops, &shop);
+    let lsm_tree = db.read();
     let books = lsm_tree.get_range(|_| true).unwrap();
-    let mut results = vec![];
-    for book in books {
-        let decode = lsm_tree.decode(&book);
-        let name = decode.get("Name").unwrap().get_text();
-        let author = decode.get("Author").unwrap().get_text();
-        let year = decode.get("Year").unwrap().get_int();
-        let in_stock = decode.get("Quantity").unwrap().get_int();
-
-        let book = Book {
-            name,
-            author,
-            year: year.to_string(),
-            quantity: in_stock.to_string(),
-        };
+    books.iter().map(|book| book_json(&lsm_tree, book)).collect()
+}
 
-        results.push(serde_json::to_string(&book).unwrap())
+/// Fetches a page of at most `limit` books in name order.
+/// `direction` tells where the page is relative to `key`: "from" starts at it, "after" starts after it,
+/// and "before" ends right before it. Without a key, the page is the first one.
+#[tauri::command]
+fn fetch_book_page(shops: State<'_, Shops>, shop: String, key: Option<String>, direction: String, limit: usize) -> Vec<String> {
+    let db = open_shop(&shops, &shop);
+    let lsm_tree = db.read();
+    let mut cursor = lsm_tree.cursor();
+    let mut books = vec![];
+
+    match (key, direction.as_str()) {
+        (Some(key), "before") => {
+            // Read the books before the key backwards, then put them back in order
+            cursor.seek_for_prev(key.as_bytes()).unwrap();
+            if cursor.key() == Some(key.as_bytes()) {
+                cursor.prev().unwrap();
+            }
+            while books.len() < limit && cursor.is_valid() {
+                books.push(book_json(&lsm_tree, cursor.value().unwrap()));
+                cursor.prev().unwrap();
+            }
+            books.reverse();
+        }
+        (key, direction) => {
+            match key {
+                Some(key) => {
+                    cursor.seek(key.as_bytes()).unwrap();
+                    if direction == "after" && cursor.key() == Some(key.as_bytes()) {
+                        cursor.next().unwrap();
+                    }
+                }
+                None => cursor.seek_to_first().unwrap(),
+            }
+            while books.len() < limit && cursor.is_valid() {
+                books.push(book_json(&lsm_tree, cursor.value().unwrap()));
+                cursor.next().unwrap();
+            }
+        }
     }
 
-    results
+    books
 }
 
 #[tauri::command]
-fn debug_print(shop: String) -> String {
-    let lsm_tree = LsmTree::load(format!("shops/{}", shop)).unwrap();
+fn debug_print(shops: State<'_, Shops>, shop: String) -> String {
+    let db = open_shop(&shops, &shop);
+    let lsm_tree = db.read();
     format!("{:#?}", lsm_tree)
 }
 
 #[tauri::command]
-fn get_log(shop: String) -> String {
-    let _ = LsmTree::load(format!("shops/{}", shop)).unwrap();
+fn get_log(shops: State<'_, Shops>, shop: String) -> String {
+    let _ = open_shop(&shops, &shop);
     let content = fs::read(format!("shops/{}/log.txt", shop)).unwrap();
     let text = String::from_utf8_lossy(&content).to_string();
     text
 }
 
 #[tauri::command]
-fn execute_sql(shop: String, query: String) -> String {
-    let mut shop = LsmTree::load(format!("shops/{}", shop)).unwrap();
+fn execute_sql(shops: State<'_, Shops>, shop: String, query: String) -> String {
+    let shop = open_shop(&shops, &shop);
     // Little sql parser with insert, select and delete. Since it is a simple parser, it is case insensitive and we only work on one table so no need for into
     if query.starts_with("insert") {
         let values: Vec<&str> = query.split_whitespace().skip(1).collect();
@@ -213,9 +298,13 @@ fn execute_sql(shop: String, query: String) -> String {
             table.add_row(Row::new(row));
 
             // Get all book with get_range returning always true for the predicate
+            let shop = shop.read();
             let books = shop.get_range(|_| true).unwrap();
             for book in books {
-                let decode = shop.decode(&book);
+                let decode = match shop.decode(&book) {
+                    Ok(decode) => decode,
+                    Err(err) => return format!("Cannot decode a book: {}", err),
+                };
                 let mut row: Vec<Cell> = vec![];
                 for column in &header {
                     let value = decode.get(&column.to_string()).unwrap();
@@ -275,6 +364,7 @@ fn execute_sql(shop: String, query: String) -> String {
 
 fn main() {
     tauri::Builder::default()
+        .manage(Shops::default())
         .invoke_handler(tauri::generate_handler![
             greet,
             get_shops,
@@ -284,6 +374,7 @@ fn main() {
             sell_book,
             remove_book,
             fetch_book,
+            fetch_book_page,
             new_book,
             debug_print,
             get_log,
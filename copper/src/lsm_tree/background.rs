@@ -0,0 +1,685 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    ops::Bound,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    thread::{self, JoinHandle},
+};
+
+use super::{
+    compaction::{self, Compaction, CompactionStats, Purge},
+    entry::{Entry, Lookup},
+    error::{CopperError, Result},
+    iterator::MergeIterator,
+    log::{write_log, Operation},
+    manifest::{Manifest, VersionEdit},
+    memtable::Memtable,
+    merge::Merger,
+    options::Options,
+    sstable::SSTable,
+};
+
+/// `Version` struct is a view of the memtable taking the writes, of the full memtables waiting to be flushed and of the SSTables of a table.
+///
+/// A shared `Version` never changes but for the writes its memtable takes, which readers skip by sequence number:
+/// every flush and compaction installs a new one, so a reader keeps a consistent view for as long as it goes through it.
+#[derive(Default, Clone)]
+pub struct Version {
+    memtable: Arc<Memtable>,
+    immutables: Vec<Arc<Memtable>>,
+    levels: Vec<Vec<Arc<SSTable>>>,
+    // The SSTables of each level below the first one in key order, or `None` if their keys overlap
+    key_ordered: Vec<Option<Vec<Arc<SSTable>>>>,
+}
+
+impl Version {
+    /// Creates a new `Version`.
+    ///
+    /// # Arguments
+    ///
+    /// * `memtable` - The memtable taking the writes.
+    /// * `immutables` - The full memtables waiting to be flushed, the newest first.
+    /// * `levels` - The SSTables of each level, the newest first.
+    pub fn new(memtable: Arc<Memtable>, immutables: Vec<Arc<Memtable>>, levels: Vec<Vec<Arc<SSTable>>>) -> Self {
+        let mut version = Self { memtable, immutables, levels, key_ordered: Vec::new() };
+        version.order_levels();
+        version
+    }
+
+    /// Sorts the SSTables of each level below the first one by key, for `get` to find the one that may hold a key,
+    /// to call once the levels changed.
+    /// A level whose SSTables overlap is left out. Two SSTables sharing a bound do not overlap, a range tombstone cut at the first key of the next one ends there.
+    fn order_levels(&mut self) {
+        self.key_ordered = self
+            .levels
+            .iter()
+            .enumerate()
+            .map(|(level, sstables)| {
+                let mut ordered: Vec<Arc<SSTable>> = sstables.iter().filter(|sstable| !sstable.is_empty()).cloned().collect();
+                ordered.sort_by(|a, b| a.get_first_key().cmp(b.get_first_key()));
+                (level > 0 && ordered.windows(2).all(|pair| pair[0].get_last_key() <= pair[1].get_first_key())).then_some(ordered)
+            })
+            .collect();
+    }
+
+    /// Returns the memtable taking the writes.
+    pub fn get_memtable(&self) -> &Arc<Memtable> {
+        &self.memtable
+    }
+
+    /// Returns the full memtables waiting to be flushed, the newest first.
+    pub fn get_immutables(&self) -> &[Arc<Memtable>] {
+        &self.immutables
+    }
+
+    /// Returns the SSTables of each level, the newest first.
+    pub fn get_levels(&self) -> &[Vec<Arc<SSTable>>] {
+        &self.levels
+    }
+
+    /// Looks up a key in the memtables, the newest first, then in the SSTables, ignoring the writes newer than a sequence number.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A byte slice that holds the key.
+    /// * `sequence` - The sequence number of the last write to see.
+    ///
+    /// Only the SSTables whose keys span the key are looked into: every `SSTable` of the first level,
+    /// and in a level below, the single one found by binary search, as the keys of its SSTables do not overlap.
+    /// The SSTables of a level written by `SimpleCompaction`, or before `LeveledCompaction`, can overlap, they are all looked into.
+    /// The newest version of the key in the SSTables is the one with the highest sequence number,
+    /// wherever its `SSTable` sits in the levels.
+    pub fn get(&self, key: &[u8], sequence: u64) -> Result<Lookup> {
+        // The memtables hold newer writes than the SSTables
+        for memtable in self.memtables() {
+            let lookup = memtable.get_at(key, sequence);
+            if lookup != Lookup::Absent {
+                return Ok(lookup);
+            }
+        }
+
+        let mut sstables: Vec<&Arc<SSTable>> = Vec::new();
+        for (level, level_sstables) in self.levels.iter().enumerate() {
+            match self.key_ordered.get(level).and_then(Option::as_ref) {
+                Some(ordered) => {
+                    let first = ordered.partition_point(|sstable| sstable.get_last_key() < key);
+                    sstables.extend(ordered[first..].iter().take_while(|sstable| sstable.get_first_key() <= key));
+                }
+                None => sstables.extend(level_sstables),
+            }
+        }
+
+        // Check the SSTables holding the newest writes first,
+        // and stop once the remaining ones can only hold older versions than the one found
+        sstables.sort_by_key(|sstable| std::cmp::Reverse(sstable.get_max_sequence()));
+
+        let mut newest = Lookup::Absent;
+        for sstable in sstables {
+            if newest.get_sequence().is_some_and(|found| sstable.get_max_sequence() < found) {
+                break;
+            }
+
+            let lookup = sstable.get_at(key, sequence)?;
+            if lookup.get_sequence() > newest.get_sequence() {
+                newest = lookup;
+            }
+        }
+
+        Ok(newest)
+    }
+
+    /// Returns iterators over the entries of the memtables and of the SSTables between two bounds,
+    /// the newest first, to merge with a `MergeIterator`.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The lower bound of the keys.
+    /// * `end` - The upper bound of the keys.
+    /// * `reverse` - Whether to go through the entries in reverse key order.
+    /// * `sequence` - The sequence number of the last write to see, newer entries are skipped.
+    pub fn sources(&self, start: Bound<&[u8]>, end: Bound<&[u8]>, reverse: bool, sequence: u64) -> Result<Vec<Box<dyn Iterator<Item = Result<Entry>>>>> {
+        let mut sources: Vec<Box<dyn Iterator<Item = Result<Entry>>>> = Vec::new();
+        for memtable in self.memtables() {
+            sources.push(Box::new(memtable.range(start, end, reverse, sequence).map(Ok)));
+        }
+        for sstable in self.levels.iter().flatten() {
+            let entries = if reverse { sstable.range_rev(start, end)? } else { sstable.range(start, end)? };
+            sources.push(Box::new(entries.filter(move |entry| entry.as_ref().map_or(true, |entry| entry.get_sequence() <= sequence))));
+        }
+
+        Ok(sources)
+    }
+
+    /// Returns the range tombstones of the memtables and of the SSTables, ignoring the ones newer than a sequence number,
+    /// to give to a `MergeIterator` along with the `sources`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sequence` - The sequence number of the last write to see.
+    pub fn range_tombstones(&self, sequence: u64) -> Vec<Entry> {
+        let memtables = self.memtables().flat_map(|memtable| memtable.get_range_tombstones());
+        let sstables = self.levels.iter().flatten().flat_map(|sstable| sstable.get_range_tombstones()).cloned();
+        memtables.chain(sstables).filter(|tombstone| tombstone.get_sequence() <= sequence).collect()
+    }
+
+    /// Returns the memtable taking the writes, then the full memtables, the newest first.
+    fn memtables(&self) -> impl Iterator<Item = &Arc<Memtable>> {
+        std::iter::once(&self.memtable).chain(&self.immutables)
+    }
+}
+
+impl std::fmt::Debug for Version {
+    /// Formats the `Version` for printing.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Version").field("memtable", &self.memtable).field("immutables", &self.immutables).field("levels", &self.levels).finish()
+    }
+}
+
+/// `SnapshotPin` struct keeps the versions of the keys seen by a `Snapshot` from being dropped by the writes to the memtable
+/// and by compactions, until it is dropped.
+pub struct SnapshotPin {
+    shared: Arc<Shared>,
+    sequence: u64,
+}
+
+impl SnapshotPin {
+    /// Returns the sequence number of the last write seen by the snapshot.
+    pub fn get_sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Returns the `Version` of the table to read at the sequence number of the snapshot:
+    /// the current one, or if the table was cleared since the snapshot was taken, the one the first clear replaced.
+    pub fn get_version(&self) -> Arc<Version> {
+        let state = self.shared.lock();
+        let cleared = state.cleared.iter().find(|(sequence, _)| *sequence > self.sequence);
+        Arc::clone(cleared.map_or(&state.version, |(_, version)| version))
+    }
+}
+
+impl Drop for SnapshotPin {
+    /// Lets the writes and compactions drop the versions only the snapshot could see,
+    /// and forgets the `Version`s replaced by clears that no snapshot reads anymore.
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        if let Some(count) = state.snapshots.get_mut(&self.sequence) {
+            *count -= 1;
+            if *count == 0 {
+                state.snapshots.remove(&self.sequence);
+            }
+        }
+        let oldest = state.snapshots.keys().next().copied();
+        state.cleared.retain(|(sequence, _)| oldest.is_some_and(|oldest| oldest < *sequence));
+    }
+}
+
+/// `Background` struct runs the flushes and the compactions of a table on a worker thread.
+///
+/// Full memtables are queued with their log, the worker writes them to SSTables in front of the first level, the oldest first,
+/// then runs the compactions picked by the `CompactionStrategy` of the table until it picks none.
+/// Writers stall in `make_room` while the worker is too far behind, see `Options`.
+/// The first error of the worker stops it, it is then returned by every call waiting for the worker.
+pub struct Background {
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<()>>,
+}
+
+/// State of a `Background` shared with its worker.
+struct Shared {
+    path: PathBuf,
+    options: Options,
+    merger: Arc<Merger>,
+    next_file_id: AtomicU64,
+    state: Mutex<State>,
+    /// Signaled when there is work for the worker, or when it must stop.
+    work: Condvar,
+    /// Signaled when the worker finishes a flush or a compaction.
+    progress: Condvar,
+}
+
+/// Mutable state of a `Background`, the logs of the full memtables are in the same order as the memtables.
+/// An idle `Background` has no MANIFEST.
+/// The open snapshots are counted by sequence number.
+struct State {
+    version: Arc<Version>,
+    logs: Vec<PathBuf>,
+    manifest: Option<Manifest>,
+    snapshots: BTreeMap<u64, usize>,
+    /// The `Version` each clear replaced with the sequence number of the clear, the oldest first, kept for the snapshots taken before it.
+    cleared: Vec<(u64, Arc<Version>)>,
+    /// The sequence number of the last clear since the worker was started, 0 if none.
+    truncated: u64,
+    stats: CompactionStats,
+    busy: bool,
+    error: Option<Arc<CopperError>>,
+    closed: bool,
+}
+
+/// `Job` enum is a unit of work of the worker of a `Background`.
+enum Job {
+    /// Writes a full memtable to an SSTable.
+    Flush(Arc<Memtable>),
+    /// Runs a compaction picked in the given `Version`.
+    Compact(Arc<Version>, Compaction),
+}
+
+impl Background {
+    /// Starts the worker of a table.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A reference to the path of the table.
+    /// * `options` - The `Options` of the table.
+    /// * `merger` - The `Merger` of the table, folding merge operands during compactions.
+    /// * `version` - The memtables and the SSTables of the table.
+    /// * `logs` - The log of each full memtable, in the same order.
+    /// * `manifest` - The open MANIFEST of the table.
+    /// * `next_file_id` - The id to give to the next SSTable file.
+    pub fn start(path: &Path, options: Options, merger: Arc<Merger>, version: Version, logs: Vec<PathBuf>, manifest: Manifest, next_file_id: u64) -> Self {
+        let shared = Shared::new(path, options, merger, version, logs, Some(manifest), next_file_id);
+
+        let worker = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || shared.run())
+        };
+
+        Self { shared, worker: Some(worker) }
+    }
+
+    /// Creates the `Background` of a table opened read-only, which holds its `Version` but runs no worker.
+    /// Nothing must be queued or waited for.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A reference to the path of the table.
+    /// * `options` - The `Options` of the table.
+    /// * `version` - The memtables and the SSTables of the table.
+    pub fn idle(path: &Path, options: Options, version: Version) -> Self {
+        Self { shared: Shared::new(path, options, Arc::default(), version, Vec::new(), None, 0), worker: None }
+    }
+
+    /// Returns the current `Version` of the table.
+    pub fn current(&self) -> Arc<Version> {
+        Arc::clone(&self.shared.lock().version)
+    }
+
+    /// Registers a snapshot seeing the writes up to the given sequence number, see `SnapshotPin`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sequence` - The sequence number of the last write seen by the snapshot.
+    pub fn pin(&self, sequence: u64) -> SnapshotPin {
+        *self.shared.lock().snapshots.entry(sequence).or_default() += 1;
+        SnapshotPin { shared: Arc::clone(&self.shared), sequence }
+    }
+
+    /// Returns the sequence numbers of the open snapshots, in increasing order.
+    pub fn get_snapshots(&self) -> Vec<u64> {
+        self.shared.lock().snapshots.keys().copied().collect()
+    }
+
+    /// Returns the sequence number of the last clear since the worker was started, 0 if none.
+    pub fn get_truncated(&self) -> u64 {
+        self.shared.lock().truncated
+    }
+
+    /// Returns what the compactions did since the worker was started.
+    pub fn get_compaction_stats(&self) -> CompactionStats {
+        self.shared.lock().stats
+    }
+
+    /// Queues the memtable of the current `Version`, which is full, to be flushed, and replaces it with a new one.
+    ///
+    /// # Arguments
+    ///
+    /// * `memtable` - The new memtable taking the writes.
+    /// * `log` - The path of the log holding the writes of the full memtable, deleted once it is flushed.
+    pub fn push(&self, memtable: Arc<Memtable>, log: PathBuf) {
+        let mut state = self.shared.lock();
+        let mut version = Version::clone(&state.version);
+        let full = std::mem::replace(&mut version.memtable, memtable);
+        version.immutables.insert(0, full);
+        state.version = Arc::new(version);
+        state.logs.insert(0, log);
+        self.shared.work.notify_one();
+    }
+
+    /// Stalls until a new full memtable can be queued:
+    /// while `max_immutable_memtables` memtables wait to be flushed,
+    /// or while the first level holds `level0_stop_writes` SSTables and a compaction is pending.
+    pub fn make_room(&self) -> Result<()> {
+        let options = &self.shared.options;
+        self.wait_until(|state| {
+            let level0 = state.version.levels.first().map_or(0, Vec::len);
+            state.version.immutables.len() < options.max_immutable_memtables.max(1) && (level0 < options.level0_stop_writes || !state.busy && self.shared.pending_compaction(state).is_none())
+        })
+    }
+
+    /// Waits until every queued memtable is flushed.
+    pub fn wait_for_flush(&self) -> Result<()> {
+        self.wait_until(|state| state.version.immutables.is_empty())
+    }
+
+    /// Waits until every queued memtable is flushed and no compaction is left to run.
+    pub fn wait_for_compaction(&self) -> Result<()> {
+        self.wait_until(|state| state.version.immutables.is_empty() && !state.busy && self.shared.pending_compaction(state).is_none())
+    }
+
+    /// Records a clear in the MANIFEST, then forgets the memtables and the SSTables and deletes their files.
+    /// Once recorded, the writes older than the clear are ignored when the table is loaded,
+    /// so the files left behind by a crash are never read again and are deleted on the next load.
+    /// A flush or a compaction running meanwhile is dropped when it finishes.
+    /// The snapshots taken before the clear go on reading the `Version` it replaced, see `SnapshotPin::get_version`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sequence` - The sequence number of the clear, above the one of every write it removes.
+    /// * `memtable` - The new memtable taking the writes.
+    pub fn clear(&self, sequence: u64, memtable: Arc<Memtable>) -> Result<()> {
+        let mut state = self.shared.lock();
+        state.manifest.as_mut().ok_or(CopperError::ReadOnly)?.append(&[VersionEdit::Truncate { sequence }])?;
+        state.truncated = sequence;
+        let version = std::mem::replace(&mut state.version, Arc::new(Version::new(memtable, Vec::new(), Vec::new())));
+        if state.snapshots.keys().next().is_some_and(|&oldest| oldest < sequence) {
+            state.cleared.push((sequence, Arc::clone(&version)));
+        }
+        let logs = std::mem::take(&mut state.logs);
+        drop(state);
+
+        // The files of the SSTables still read by a snapshot or an iterator are deleted once they are dropped
+        version.levels.iter().flatten().for_each(|sstable| sstable.mark_obsolete());
+        for log in logs {
+            fs::remove_file(log)?;
+        }
+
+        Ok(())
+    }
+
+    /// Waits until the condition holds for the state of the worker.
+    /// Returns the error of the worker if it failed.
+    fn wait_until(&self, condition: impl Fn(&State) -> bool) -> Result<()> {
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(err) = &state.error {
+                return Err(CopperError::Background(Arc::clone(err)));
+            }
+            if condition(&state) {
+                return Ok(());
+            }
+
+            state = self.shared.progress.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+}
+
+impl Shared {
+    /// Creates the state shared by a `Background` and its worker.
+    fn new(path: &Path, options: Options, merger: Arc<Merger>, version: Version, logs: Vec<PathBuf>, manifest: Option<Manifest>, next_file_id: u64) -> Arc<Self> {
+        let state = State { version: Arc::new(version), logs, manifest, snapshots: BTreeMap::new(), cleared: Vec::new(), truncated: 0, stats: CompactionStats::default(), busy: false, error: None, closed: false };
+        Arc::new(Self { path: path.to_path_buf(), options, merger, next_file_id: AtomicU64::new(next_file_id), state: Mutex::new(state), work: Condvar::new(), progress: Condvar::new() })
+    }
+
+    /// Locks the state, recovering it if a thread panicked while holding it.
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Body of the worker, runs jobs until the table is closed or a job fails.
+    fn run(&self) {
+        let mut state = self.lock();
+        while !state.closed && state.error.is_none() {
+            let Some(job) = self.next_job(&state) else {
+                state = self.work.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+                continue;
+            };
+
+            // Run the job without holding the lock, so readers and writers can go on
+            state.busy = true;
+            drop(state);
+            let result = match job {
+                Job::Flush(memtable) => self.flush(&memtable),
+                Job::Compact(version, compaction) => self.compact(&version, &compaction),
+            };
+
+            state = self.lock();
+            state.busy = false;
+            if let Err(err) = result {
+                state.error = Some(Arc::new(err));
+            }
+            self.progress.notify_all();
+        }
+    }
+
+    /// Returns the next job: flushing the oldest full memtable first, then the next compaction.
+    fn next_job(&self, state: &State) -> Option<Job> {
+        if let Some(memtable) = state.version.immutables.last() {
+            return Some(Job::Flush(Arc::clone(memtable)));
+        }
+
+        Some(Job::Compact(Arc::clone(&state.version), self.pending_compaction(state)?))
+    }
+
+    /// Returns the compaction the `CompactionStrategy` of the table picks in the current `Version`, if any.
+    fn pending_compaction(&self, state: &State) -> Option<Compaction> {
+        self.options.compaction.pick(&state.version.levels).filter(|compaction| !compaction.get_inputs().is_empty())
+    }
+
+    /// Writes a full memtable to a new SSTable file in front of the first level and records it in the MANIFEST.
+    /// Once the SSTable is part of the table, deletes the log of the memtable.
+    fn flush(&self, memtable: &Arc<Memtable>) -> Result<()> {
+        let id = self.next_file_id.fetch_add(1, Ordering::SeqCst);
+        let sstable = Arc::new(SSTable::from_memtable(memtable, &sstable_path(&self.path, 0, id), &self.options)?);
+
+        let mut state = self.lock();
+        // The memtable is gone if the table was cleared meanwhile
+        let Some(position) = state.version.immutables.iter().position(|immutable| Arc::ptr_eq(immutable, memtable)) else {
+            sstable.mark_obsolete();
+            return Ok(());
+        };
+        state.manifest.as_mut().ok_or(CopperError::ReadOnly)?.append(&[VersionEdit::Add { level: 0, id }])?;
+
+        let mut version = Version::clone(&state.version);
+        version.immutables.remove(position);
+        if version.levels.is_empty() {
+            version.levels.push(Vec::new());
+        }
+        version.levels[0].insert(0, sstable);
+        state.version = Arc::new(version);
+        let log = state.logs.remove(position);
+        drop(state);
+
+        // The memtable is now persisted, its log is no longer needed
+        fs::remove_file(log)?;
+
+        write_log(&self.path, Operation::Flush)?;
+
+        Ok(())
+    }
+
+    /// Runs a compaction: merges its SSTables into a new SSTable file, or files of about its target size, in front of its output level.
+    /// Older versions of keys no snapshot sees, and tombstones with no older version left to hide, are dropped, see `Purge`.
+    /// A snapshot opened meanwhile sees the newest version of every key, which is always kept.
+    /// The new files and the merged ones are swapped in the MANIFEST as a single edit and in a new `Version`,
+    /// the merged files are deleted once no reader goes through them.
+    fn compact(&self, version: &Version, compaction: &Compaction) -> Result<()> {
+        let output_level = compaction.get_output_level();
+        let inputs: Vec<&Arc<SSTable>> = compaction.get_inputs().iter().map(|&(level, position)| &version.levels[level][position]).collect();
+
+        // Only the SSTables left out that hold keys in the range of the merged ones can hold older versions of them
+        let mut others = Vec::new();
+        if let Some((first_key, last_key)) = compaction::key_range(inputs.iter().map(|sstable| sstable.as_ref())) {
+            for (level, sstables) in version.levels.iter().enumerate() {
+                for (position, sstable) in sstables.iter().enumerate() {
+                    if !compaction.get_inputs().contains(&(level, position)) && sstable.overlaps(first_key, last_key) {
+                        others.push(sstable.as_ref());
+                    }
+                }
+            }
+        }
+
+        // Merge the SSTables, the newest first, and cut the result into files of about the target size if there is one
+        let mut sources: Vec<Box<dyn Iterator<Item = Result<Entry>>>> = Vec::new();
+        let mut range_tombstones = Vec::new();
+        for sstable in &inputs {
+            sources.push(Box::new(sstable.iter()?));
+            range_tombstones.extend_from_slice(sstable.get_range_tombstones());
+        }
+        let mut versions = MergeIterator::new(sources, false).with_all_versions();
+        let snapshots = self.lock().snapshots.keys().copied().collect();
+        let mut entries = Purge::new(std::iter::from_fn(|| versions.next_entry()), others, snapshots, &self.merger).with_range_tombstones(range_tombstones);
+
+        // The range tombstones still needed are cut at the first key of each new file,
+        // so each file gets the parts within its own keys and the files of a level do not overlap
+        let range_tombstones = entries.get_range_tombstones().to_vec();
+        let mut lower: Option<Vec<u8>> = None;
+        let mut write = |entries: Vec<Entry>, upper: Option<&[u8]>| -> Result<(u64, Arc<SSTable>)> {
+            let pieces = range_tombstones.iter().filter_map(|tombstone| tombstone.clip(lower.as_deref(), upper)).collect();
+            lower = upper.map(<[u8]>::to_vec);
+            let id = self.next_file_id.fetch_add(1, Ordering::SeqCst);
+            Ok((id, Arc::new(SSTable::create(&sstable_path(&self.path, output_level, id), entries, pieces, &self.options)?)))
+        };
+
+        let mut outputs = Vec::new();
+        let mut batch = Vec::new();
+        let mut batch_size = 0;
+        for entry in entries.by_ref() {
+            // The versions of a key stay in the same file
+            let entry = entry?;
+            let full = compaction.get_target_file_size().is_some_and(|target_file_size| batch_size >= target_file_size);
+            if full && batch.last().is_some_and(|last: &Entry| last.get_key() != entry.get_key()) {
+                outputs.push(write(std::mem::take(&mut batch), Some(entry.get_key()))?);
+                batch_size = 0;
+            }
+            batch_size += entry.get_key().len() + entry.get_value().len() + 1;
+            batch.push(entry);
+        }
+        if !batch.is_empty() || (outputs.is_empty() && !entries.get_range_tombstones().is_empty()) {
+            outputs.push(write(batch, None)?);
+        }
+        let stats = entries.get_stats();
+
+        let mut state = self.lock();
+        // The merged SSTables are gone if the table was cleared meanwhile, then so are the new ones
+        let mut current = Version::clone(&state.version);
+        let mut merged = Vec::new();
+        for (&(level, _), input) in compaction.get_inputs().iter().zip(&inputs) {
+            let Some(position) = current.levels.get(level).and_then(|sstables| sstables.iter().position(|sstable| Arc::ptr_eq(sstable, input))) else {
+                outputs.iter().for_each(|(_, sstable)| sstable.mark_obsolete());
+                return Ok(());
+            };
+            merged.push((level, current.levels[level].remove(position)));
+        }
+
+        // Swap the files in the MANIFEST at once
+        let mut edits: Vec<VersionEdit> = outputs.iter().map(|&(id, _)| VersionEdit::Add { level: output_level, id }).collect();
+        edits.extend(merged.iter().map(|(level, sstable)| VersionEdit::Remove { level: *level, id: sstable_id(sstable) }));
+        if let Err(err) = state.manifest.as_mut().ok_or(CopperError::ReadOnly).and_then(|manifest| manifest.append(&edits)) {
+            outputs.iter().for_each(|(_, sstable)| sstable.mark_obsolete());
+            return Err(err);
+        }
+
+        // Add the new SSTables in front of the output level, in key order
+        while current.levels.len() <= output_level {
+            current.levels.push(Vec::new());
+        }
+        for (_, sstable) in outputs.into_iter().rev() {
+            current.levels[output_level].insert(0, sstable);
+        }
+        current.order_levels();
+        state.version = Arc::new(current);
+        state.stats += stats;
+        drop(state);
+
+        // Their entries are all in the new SSTables, the merged files can go once no reader needs them
+        for (_, sstable) in merged {
+            sstable.mark_obsolete();
+        }
+
+        write_log(&self.path, Operation::Compact(stats.get_tombstones_purged(), stats.get_versions_purged()))?;
+
+        Ok(())
+    }
+}
+
+impl Drop for Background {
+    /// Stops the worker once its current job is done.
+    /// Full memtables left in the queue are flushed from their logs when the table is opened again.
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.work.notify_all();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Returns the path of the file of an SSTable from its level and its id, `ssts/sst_<level>_<id>.sst`.
+pub fn sstable_path(table_path: &Path, level: usize, id: u64) -> PathBuf {
+    table_path.join("ssts").join(format!("sst_{}_{}.sst", level, id))
+}
+
+/// Returns the id of an SSTable from the name of its file, `sst_<level>_<id>.sst`.
+fn sstable_id(sstable: &SSTable) -> u64 {
+    let name = sstable.get_path().file_stem().unwrap_or_default().to_string_lossy();
+    name.rsplit('_').next().and_then(|id| id.parse().ok()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes an `SSTable` holding the given keys, values and sequence numbers, an empty value being a tombstone.
+    fn sstable(dir: &Path, id: u64, entries: &[(&[u8], &[u8], u64)]) -> Arc<SSTable> {
+        let entries = entries.iter().map(|&(key, value, sequence)| Entry::new(key, value, value.is_empty(), sequence));
+        Arc::new(SSTable::create(&dir.join(format!("sst_{}.sst", id)), entries, Vec::new(), &Options::default()).unwrap())
+    }
+
+    fn found(value: &[u8], sequence: u64) -> Lookup {
+        Lookup::Found { value: value.to_vec(), sequence }
+    }
+
+    #[test]
+    fn finds_the_newest_version_through_the_levels() {
+        let dir = tempfile::tempdir().unwrap();
+        let level0 = vec![sstable(dir.path(), 0, &[(b"a", b"new", 5), (b"b", b"", 6)])];
+        let level1 = vec![sstable(dir.path(), 1, &[(b"a", b"old", 1), (b"b", b"old", 2), (b"c", b"old", 3)]), sstable(dir.path(), 2, &[(b"d", b"old", 4)])];
+        let version = Version::new(Arc::default(), Vec::new(), vec![level0, level1]);
+
+        // The first level shadows the older versions below, a tombstone included
+        assert_eq!(version.get(b"a", u64::MAX).unwrap(), found(b"new", 5));
+        assert_eq!(version.get(b"b", u64::MAX).unwrap(), Lookup::Deleted { sequence: 6 });
+        assert_eq!(version.get(b"c", u64::MAX).unwrap(), found(b"old", 3));
+        assert_eq!(version.get(b"e", u64::MAX).unwrap(), Lookup::Absent);
+
+        // An older point in time sees the older versions
+        assert_eq!(version.get(b"a", 4).unwrap(), found(b"old", 1));
+        assert_eq!(version.get(b"b", 5).unwrap(), found(b"old", 2));
+    }
+
+    #[test]
+    fn looks_into_a_single_sstable_of_a_level() {
+        let dir = tempfile::tempdir().unwrap();
+        let level1 = vec![sstable(dir.path(), 0, &[(b"e", b"e", 3), (b"f", b"f", 4)]), sstable(dir.path(), 1, &[(b"a", b"a", 1), (b"b", b"b", 2)])];
+        let version = Version::new(Arc::default(), Vec::new(), vec![Vec::new(), level1.clone()]);
+
+        // Once the SSTable holding e is unreadable, the keys of the other one are still found without reading it
+        fs::write(level1[0].get_path(), b"corrupted").unwrap();
+        assert_eq!(version.get(b"a", u64::MAX).unwrap(), found(b"a", 1));
+        assert_eq!(version.get(b"c", u64::MAX).unwrap(), Lookup::Absent);
+        assert!(version.get(b"e", u64::MAX).is_err());
+    }
+
+    #[test]
+    fn looks_into_every_sstable_of_an_overlapping_level() {
+        let dir = tempfile::tempdir().unwrap();
+        let level1 = vec![sstable(dir.path(), 0, &[(b"b", b"new", 3)]), sstable(dir.path(), 1, &[(b"a", b"old", 1), (b"b", b"old", 2), (b"c", b"old", 2)])];
+        let version = Version::new(Arc::default(), Vec::new(), vec![Vec::new(), level1]);
+
+        assert_eq!(version.get(b"b", u64::MAX).unwrap(), found(b"new", 3));
+        assert_eq!(version.get(b"c", u64::MAX).unwrap(), found(b"old", 2));
+    }
+}
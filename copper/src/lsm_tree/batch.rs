@@ -0,0 +1,141 @@
+/// `BatchOperation` enum represents a write recorded in a `WriteBatch`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchOperation {
+    /// Inserts a key with the values of its columns, in order.
+    Put(Vec<u8>, Vec<Vec<u8>>),
+    /// Deletes a key.
+    Delete(Vec<u8>),
+    /// Deletes every key from the first one, included, to the second one, excluded.
+    DeleteRange(Vec<u8>, Vec<u8>),
+}
+
+/// `WriteBatch` struct gathers writes to apply to a table at once with `LsmTree::write_batch`.
+///
+/// The writes of a batch are applied in the order they were added and are written to the WAL as a single record,
+/// so after a crash either all of them are in the table or none of them is.
+#[derive(Debug, Default, Clone)]
+pub struct WriteBatch {
+    operations: Vec<BatchOperation>,
+}
+
+impl WriteBatch {
+    /// Creates an empty `WriteBatch`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the insertion of a key-value pair to the batch.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A byte slice that holds the key.
+    /// * `values` - A slice of byte vectors that holds the values of the columns, in order.
+    pub fn put(&mut self, key: &[u8], values: &[Vec<u8>]) {
+        self.operations.push(BatchOperation::Put(key.to_vec(), values.to_vec()));
+    }
+
+    /// Adds the deletion of a key to the batch.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A byte slice that holds the key.
+    pub fn delete(&mut self, key: &[u8]) {
+        self.operations.push(BatchOperation::Delete(key.to_vec()));
+    }
+
+    /// Adds the deletion of a range of keys to the batch.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - A byte slice that holds the first key of the range, included.
+    /// * `end` - A byte slice that holds the end of the range, excluded.
+    pub fn delete_range(&mut self, start: &[u8], end: &[u8]) {
+        self.operations.push(BatchOperation::DeleteRange(start.to_vec(), end.to_vec()));
+    }
+
+    /// Returns the writes of the batch, in the order they were added.
+    pub fn get_operations(&self) -> &[BatchOperation] {
+        &self.operations
+    }
+
+    /// Returns the number of writes in the batch.
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    /// Returns true if the batch holds no write.
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Removes every write from the batch, so it can be reused.
+    pub fn clear(&mut self) {
+        self.operations.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::lsm_tree::{
+        column::{Column, DataType},
+        entry::Lookup,
+        options::Options,
+        tree::LsmTree,
+        wal,
+    };
+
+    /// Creates a table holding `x`, then writes a batch inserting `y` and `z` and deleting `x`, and closes it.
+    fn write_table(path: &std::path::Path) -> String {
+        let path = path.join("table").to_string_lossy().into_owned();
+        let options = Options { memtable_size: 4096, ..Options::default() };
+        let mut table = LsmTree::create(path.clone(), vec![Column::new("Name", DataType::Text)], options).unwrap();
+        table.insert(b"x", &[b"x".to_vec()]).unwrap();
+
+        let mut batch = WriteBatch::new();
+        batch.put(b"y", &[b"y".to_vec()]);
+        batch.put(b"z", &[b"z".to_vec()]);
+        batch.delete(b"x");
+        table.write_batch(&batch).unwrap();
+        path
+    }
+
+    #[test]
+    fn discards_a_torn_batch_whole() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_table(dir.path());
+
+        // The batch record is cut in its middle
+        let wal_path = std::path::Path::new(&path).join("wal.txt");
+        let data = fs::read(&wal_path).unwrap();
+        fs::write(&wal_path, &data[..data.len() - 12]).unwrap();
+
+        let table = LsmTree::open(path, vec![Column::new("Name", DataType::Text)], Options::default()).unwrap();
+        assert_eq!((table.get_wal_recovery().get_recovered(), table.get_wal_recovery().get_discarded()), (1, 1));
+        assert!(table.get(b"x").unwrap().is_some());
+        assert!(table.get(b"y").unwrap().is_none());
+        assert!(table.get(b"z").unwrap().is_none());
+    }
+
+    #[test]
+    fn replays_the_sequence_numbers_of_a_batch_together() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_table(dir.path());
+
+        let (memtable, recovery) = wal::get_memtable_from_wal(path.as_ref(), 0).unwrap();
+        assert_eq!((recovery.get_recovered(), recovery.get_last_sequence()), (2, 4));
+        assert_eq!(memtable.get(b"y").get_sequence(), Some(2));
+        assert_eq!(memtable.get(b"z").get_sequence(), Some(3));
+        assert_eq!(memtable.get(b"x"), Lookup::Deleted { sequence: 4 });
+
+        // The next write after a reopen is numbered after the whole batch
+        let mut table = LsmTree::open(path.clone(), vec![Column::new("Name", DataType::Text)], Options { memtable_size: 4096, ..Options::default() }).unwrap();
+        assert!(table.get(b"x").unwrap().is_none());
+        table.insert(b"w", &[b"w".to_vec()]).unwrap();
+        drop(table);
+        let (memtable, _) = wal::get_memtable_from_wal(path.as_ref(), 0).unwrap();
+        assert_eq!(memtable.get(b"w").get_sequence(), Some(5));
+    }
+}
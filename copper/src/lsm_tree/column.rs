@@ -1,7 +1,10 @@
 use std::fmt::Debug;
 
+use super::error::{CopperError, Result};
+
 /// `Column` struct represents a column in a database table.
 /// It has a `name` and a `data_type`.
+#[derive(Clone, PartialEq, Eq)]
 pub struct Column {
     name: String,
     data_type: DataType,
@@ -10,6 +13,7 @@ pub struct Column {
 /// `DataType` enum represents the type of data that can be stored in a `Column`.
 /// It can be an `Int`, `Text`, or `Bool`.
 #[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum DataType {
     Int = 0,
     Text = 1,
@@ -46,13 +50,13 @@ impl DataType {
 
     /// Returns a `DataType` from a character.
     /// 'i' for `Int`, 't' for `Text`, 'b' for `Bool`.
-    /// Panics if the character is not 'i', 't', or 'b'.
-    pub fn from_char(c: char) -> Self {
+    /// Returns an `InvalidValue` error if the character is not 'i', 't', or 'b'.
+    pub fn from_char(c: char) -> Result<Self> {
         match c {
-            'i' => DataType::Int,
-            't' => DataType::Text,
-            'b' => DataType::Bool,
-            _ => panic!("Invalid data type character"),
+            'i' => Ok(DataType::Int),
+            't' => Ok(DataType::Text),
+            'b' => Ok(DataType::Bool),
+            _ => Err(CopperError::InvalidValue(format!("Invalid data type character {:?}", c))),
         }
     }
 }
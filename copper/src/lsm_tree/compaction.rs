@@ -0,0 +1,562 @@
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fmt::Debug,
+    ops::AddAssign,
+    sync::Arc,
+};
+
+use super::{
+    entry::{Entry, EntryKind},
+    error::Result,
+    merge::Merger,
+    sstable::SSTable,
+};
+
+/// `CompactionStrategy` trait decides which SSTables of a table are merged together, and where the result goes.
+///
+/// `LsmTree::compact` asks the strategy of the table for compactions and runs them until it has none left,
+/// the strategy is given in the `Options` the table is opened with.
+/// Copper provides `LeveledCompaction`, the default, `SizeTieredCompaction` and `SimpleCompaction`.
+pub trait CompactionStrategy: Debug + Send + Sync {
+    /// Picks the next compaction to run, or `None` if the levels need no compaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `levels` - The SSTables of each level, the newest first.
+    ///
+    /// Each compaction must leave fewer SSTables, or move them to a deeper level, so that compacting ends.
+    fn pick(&self, levels: &[Vec<Arc<SSTable>>]) -> Option<Compaction>;
+}
+
+/// `Compaction` struct describes the SSTables to merge together and the level the merged SSTables go to.
+/// Each input is given by its level and its position in the level.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Compaction {
+    inputs: Vec<(usize, usize)>,
+    output_level: usize,
+    target_file_size: Option<usize>,
+}
+
+impl Compaction {
+    /// Creates a new `Compaction`, writing the merged entries to a single `SSTable`.
+    ///
+    /// # Arguments
+    ///
+    /// * `inputs` - The level and the position in the level of every `SSTable` to merge.
+    /// * `output_level` - The level of the merged SSTables.
+    ///
+    /// The inputs are sorted by level then by position, so the newest `SSTable` comes first.
+    pub fn new(mut inputs: Vec<(usize, usize)>, output_level: usize) -> Self {
+        inputs.sort_unstable();
+        inputs.dedup();
+        Self { inputs, output_level, target_file_size: None }
+    }
+
+    /// Cuts the merged entries into SSTables of about `target_file_size` bytes instead of a single one.
+    pub fn with_target_file_size(mut self, target_file_size: usize) -> Self {
+        self.target_file_size = Some(target_file_size.max(1));
+        self
+    }
+
+    /// Returns the level and the position in the level of every `SSTable` to merge, the newest first.
+    pub fn get_inputs(&self) -> &[(usize, usize)] {
+        &self.inputs
+    }
+
+    /// Returns the level of the merged SSTables.
+    pub fn get_output_level(&self) -> usize {
+        self.output_level
+    }
+
+    /// Returns the size in bytes the merged SSTables are cut at, or `None` if they are written to a single `SSTable`.
+    pub fn get_target_file_size(&self) -> Option<usize> {
+        self.target_file_size
+    }
+}
+
+/// `LeveledCompaction` struct is a `CompactionStrategy` that keeps few SSTables to read from, at the cost of rewriting data more often.
+///
+/// The SSTables of the first level are flushed memtables whose keys overlap,
+/// once there are `level0_file_limit` of them they are all merged with the SSTables of the second level they overlap.
+/// Below, each level has a byte budget, `level_base_size` for the second level and `level_size_ratio` times more for each next one.
+/// The level furthest above its budget pushes one `SSTable` down, merged with the SSTables of the next level it overlaps,
+/// so the keys of the SSTables of a level below the first one do not overlap.
+#[derive(Debug, Clone)]
+pub struct LeveledCompaction {
+    /// The number of SSTables in the first level that triggers their compaction into the second level, 4 by default.
+    pub level0_file_limit: usize,
+    /// The byte budget of the second level, 10 MiB by default.
+    pub level_base_size: u64,
+    /// How many times larger the budget of a level is than the budget of the level above it, 10 by default.
+    /// Values below 2 are treated as 2, otherwise a level could never hold more than the one above it.
+    pub level_size_ratio: u64,
+    /// The target size in bytes of an SSTable written by a compaction, 2 MiB by default.
+    pub target_file_size: usize,
+}
+
+impl Default for LeveledCompaction {
+    /// Creates a `LeveledCompaction` with the default settings.
+    fn default() -> Self {
+        Self { level0_file_limit: 4, level_base_size: 10 * 1024 * 1024, level_size_ratio: 10, target_file_size: 2 * 1024 * 1024 }
+    }
+}
+
+impl CompactionStrategy for LeveledCompaction {
+    fn pick(&self, levels: &[Vec<Arc<SSTable>>]) -> Option<Compaction> {
+        if let Some(level0) = levels.first().filter(|level| !level.is_empty() && level.len() >= self.level0_file_limit) {
+            let mut inputs: Vec<(usize, usize)> = (0..level0.len()).map(|position| (0, position)).collect();
+            if let Some((first_key, last_key)) = key_range(level0.iter().map(Arc::as_ref)) {
+                inputs.extend(overlapping(levels, 1, first_key, last_key).map(|position| (1, position)));
+            }
+            return Some(Compaction::new(inputs, 1).with_target_file_size(self.target_file_size));
+        }
+
+        // Find the level furthest above its budget
+        let mut budget = self.level_base_size.max(1);
+        let mut fullest: Option<(usize, f64)> = None;
+        for (level, sstables) in levels.iter().enumerate().skip(1) {
+            let score = level_size(sstables) as f64 / budget as f64;
+            if score > 1.0 && fullest.is_none_or(|(_, best)| score > best) {
+                fullest = Some((level, score));
+            }
+            budget = budget.saturating_mul(self.level_size_ratio.max(2));
+        }
+        let (level, _) = fullest?;
+
+        // Push down the SSTable that overlaps the fewest bytes of the next level for its size, which rewrites the least data
+        let write_ratio = |sstable: &Arc<SSTable>| {
+            let overlap: u64 = overlapping(levels, level + 1, sstable.get_first_key(), sstable.get_last_key()).map(|position| levels[level + 1][position].get_size() as u64).sum();
+            overlap as f64 / sstable.get_size().max(1) as f64
+        };
+        let sstable = levels[level].iter().filter(|sstable| !sstable.is_empty()).min_by(|a, b| write_ratio(a).total_cmp(&write_ratio(b)))?;
+
+        // SSTables of the level that overlap it, which only a table written before leveled compaction has, move down with it
+        let mut inputs: Vec<(usize, usize)> = overlapping(levels, level, sstable.get_first_key(), sstable.get_last_key()).map(|position| (level, position)).collect();
+        let (first_key, last_key) = key_range(inputs.iter().map(|&(level, position)| levels[level][position].as_ref()))?;
+        inputs.extend(overlapping(levels, level + 1, first_key, last_key).map(|position| (level + 1, position)));
+        Some(Compaction::new(inputs, level + 1).with_target_file_size(self.target_file_size))
+    }
+}
+
+/// `SizeTieredCompaction` struct is a `CompactionStrategy` that rewrites data less often, at the cost of more SSTables to read from,
+/// which suits tables that are mostly written to.
+///
+/// Every `SSTable` is a sorted run. Runs of similar sizes are merged together into a single larger run, in the first level,
+/// once there are `min_merge` of them, so each entry is only rewritten when its run has grown a few times.
+#[derive(Debug, Clone)]
+pub struct SizeTieredCompaction {
+    /// The number of SSTables of similar sizes that triggers their merge, 4 by default.
+    pub min_merge: usize,
+    /// The largest number of SSTables merged at once, 32 by default.
+    pub max_merge: usize,
+    /// How many times larger, or smaller, than the average size of a group of SSTables an `SSTable` can be to join it, 1.5 by default.
+    pub size_ratio: f64,
+    /// The size in bytes below which SSTables are all considered similar, 1 MiB by default.
+    pub min_table_size: usize,
+}
+
+impl Default for SizeTieredCompaction {
+    /// Creates a `SizeTieredCompaction` with the default settings.
+    fn default() -> Self {
+        Self { min_merge: 4, max_merge: 32, size_ratio: 1.5, min_table_size: 1024 * 1024 }
+    }
+}
+
+impl CompactionStrategy for SizeTieredCompaction {
+    fn pick(&self, levels: &[Vec<Arc<SSTable>>]) -> Option<Compaction> {
+        let mut sstables: Vec<(usize, usize, usize)> = levels.iter().enumerate().flat_map(|(level, sstables)| sstables.iter().enumerate().map(move |(position, sstable)| (sstable.get_size(), level, position))).collect();
+        sstables.sort_unstable();
+
+        // Group the SSTables, from the smallest, while they stay close to the average size of their group
+        let mut buckets: Vec<Vec<(usize, usize, usize)>> = Vec::new();
+        for sstable in sstables {
+            let size = sstable.0 as f64;
+            let joins = buckets.last().is_some_and(|bucket| {
+                let average = bucket.iter().map(|(size, ..)| *size as f64).sum::<f64>() / bucket.len() as f64;
+                sstable.0 < self.min_table_size || size <= average * self.size_ratio.max(1.0)
+            });
+            match buckets.last_mut() {
+                Some(bucket) if joins => bucket.push(sstable),
+                _ => buckets.push(vec![sstable]),
+            }
+        }
+
+        // Merge the smallest SSTables first, they are the cheapest to merge and the most numerous
+        let min_merge = self.min_merge.max(2);
+        let bucket = buckets.into_iter().find(|bucket| bucket.len() >= min_merge)?;
+        let inputs = bucket.into_iter().take(self.max_merge.max(min_merge)).map(|(_, level, position)| (level, position)).collect();
+        Some(Compaction::new(inputs, 0))
+    }
+}
+
+/// `SimpleCompaction` struct is a `CompactionStrategy` that merges every level holding more than `max_tables` SSTables
+/// into a single `SSTable` pushed in front of the next level.
+/// It is how tables were compacted before `LeveledCompaction`, levels grow without bound and the last one is never rewritten.
+#[derive(Debug, Clone)]
+pub struct SimpleCompaction {
+    /// The number of SSTables a level can hold before it is merged, 2 by default.
+    pub max_tables: usize,
+}
+
+impl Default for SimpleCompaction {
+    /// Creates a `SimpleCompaction` with the default settings.
+    fn default() -> Self {
+        Self { max_tables: 2 }
+    }
+}
+
+impl CompactionStrategy for SimpleCompaction {
+    fn pick(&self, levels: &[Vec<Arc<SSTable>>]) -> Option<Compaction> {
+        let level = levels.iter().position(|sstables| sstables.len() > self.max_tables.max(1))?;
+        Some(Compaction::new((0..levels[level].len()).map(|position| (level, position)).collect(), level + 1))
+    }
+}
+
+/// `CompactionStats` struct reports what compactions did: how many ran, how many entries they wrote,
+/// and how many tombstones and older versions of keys they dropped.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionStats {
+    compactions: usize,
+    written: u64,
+    tombstones_purged: u64,
+    versions_purged: u64,
+}
+
+impl CompactionStats {
+    /// Returns the number of compactions run.
+    pub fn get_compactions(&self) -> usize {
+        self.compactions
+    }
+
+    /// Returns the number of entries written to the new SSTables.
+    pub fn get_written(&self) -> u64 {
+        self.written
+    }
+
+    /// Returns the number of tombstones and range tombstones dropped because no older version of their keys was left to hide.
+    pub fn get_tombstones_purged(&self) -> u64 {
+        self.tombstones_purged
+    }
+
+    /// Returns the number of versions dropped because a newer version of their key was written.
+    pub fn get_versions_purged(&self) -> u64 {
+        self.versions_purged
+    }
+}
+
+impl AddAssign for CompactionStats {
+    /// Adds the counts of other `CompactionStats`.
+    fn add_assign(&mut self, other: Self) {
+        self.compactions += other.compactions;
+        self.written += other.written;
+        self.tombstones_purged += other.tombstones_purged;
+        self.versions_purged += other.versions_purged;
+    }
+}
+
+/// `Purge` struct goes through every version of the keys merged by a compaction and only returns the ones to write,
+/// counting the dropped ones in its `CompactionStats`.
+///
+/// Only the newest version of a key is written, the older ones are hidden by it,
+/// unless an open `Snapshot` still sees them: the newest version each snapshot can see is written too.
+/// A tombstone older than every snapshot is dropped too when no `SSTable` left out of the compaction may hold the key:
+/// there is no older version left for it to hide, which is always the case once it reaches the bottom level.
+/// A written version holding merge operands is folded with the older ones by the `Merger` of the table,
+/// unless an `SSTable` left out holds a version of the key older than it and newer than every value or tombstone below it,
+/// then every version from it down is written as is.
+/// A range tombstone hides the older versions of the keys it covers like a tombstone, and is written apart, see `get_range_tombstones`.
+pub struct Purge<'a, I> {
+    entries: I,
+    others: Vec<&'a SSTable>,
+    snapshots: Vec<u64>,
+    merger: &'a Merger,
+    range_tombstones: Vec<Entry>,
+    kept: Vec<Entry>,
+    next: Option<Entry>,
+    pending: VecDeque<Entry>,
+    stats: CompactionStats,
+}
+
+impl<'a, I: Iterator<Item = Result<Entry>>> Purge<'a, I> {
+    /// Creates a new `Purge`.
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - Every version of the merged keys in key order, the newest version of a key first.
+    /// * `others` - The SSTables of the table left out of the compaction.
+    /// * `snapshots` - The sequence numbers of the open snapshots, in increasing order.
+    /// * `merger` - The `Merger` of the table.
+    pub fn new(entries: I, others: Vec<&'a SSTable>, snapshots: Vec<u64>, merger: &'a Merger) -> Self {
+        Self { entries, others, snapshots, merger, range_tombstones: Vec::new(), kept: Vec::new(), next: None, pending: VecDeque::new(), stats: CompactionStats { compactions: 1, ..Default::default() } }
+    }
+
+    /// Makes the range tombstones of the merged SSTables hide the versions they cover.
+    /// A range tombstone older than every snapshot is dropped when no `SSTable` left out of the compaction overlaps its keys,
+    /// the others are kept.
+    /// A range tombstone cut into parts by earlier compactions has the same sequence number in each part,
+    /// the parts kept that touch each other are joined back.
+    ///
+    /// # Arguments
+    ///
+    /// * `range_tombstones` - The range tombstones of the merged SSTables.
+    pub fn with_range_tombstones(mut self, range_tombstones: Vec<Entry>) -> Self {
+        for tombstone in &range_tombstones {
+            let stripe = self.snapshots.partition_point(|&snapshot| snapshot < tombstone.get_sequence());
+            if stripe == 0 && !self.others.iter().any(|sstable| sstable.overlaps(tombstone.get_key(), tombstone.get_value())) {
+                self.stats.tombstones_purged += 1;
+                continue;
+            }
+
+            let mut joined = tombstone.clone();
+            self.kept.retain(|kept| {
+                let touches = kept.get_sequence() == joined.get_sequence() && kept.get_key() <= joined.get_value() && joined.get_key() <= kept.get_value();
+                if touches {
+                    joined = Entry::with_kind(kept.get_key().min(joined.get_key()), kept.get_value().max(joined.get_value()), EntryKind::RangeTombstone, joined.get_sequence());
+                }
+                !touches
+            });
+            self.kept.push(joined);
+        }
+
+        self.range_tombstones = range_tombstones;
+        self
+    }
+
+    /// Returns the range tombstones to write along with the versions.
+    pub fn get_range_tombstones(&self) -> &[Entry] {
+        &self.kept
+    }
+
+    /// Returns the counts of the versions written and dropped so far.
+    pub fn get_stats(&self) -> CompactionStats {
+        self.stats
+    }
+
+    /// Returns true if an `SSTable` left out of the compaction may hold a version of the key.
+    fn elsewhere(&self, key: &[u8]) -> bool {
+        self.others.iter().any(|sstable| sstable.overlaps(key, key) && sstable.may_contain(key))
+    }
+
+    /// Folds a version holding merge operands with the older versions of the key merged by the compaction.
+    /// An `SSTable` left out of the compaction may hold versions in between, when the merged SSTables were not written one after the other,
+    /// so only the versions newer than the ones left out are folded.
+    /// Returns `None` if they hold only operands, see `Merger::fold`.
+    fn fold_merge(&self, versions: &[Entry]) -> Result<Option<Entry>> {
+        let key = versions[0].get_key();
+        let mut outside = None;
+        for sstable in self.others.iter().filter(|sstable| sstable.overlaps(key, key)) {
+            outside = outside.max(sstable.get_at(key, versions[0].get_sequence())?.get_sequence());
+        }
+
+        let inside = versions.iter().take_while(|version| outside.is_none_or(|outside| version.get_sequence() > outside)).count();
+        self.merger.fold(&versions[..inside], outside.is_none())
+    }
+
+    /// Reads every version of the next key, the newest first, or `None` once there is no key left.
+    fn next_versions(&mut self) -> Result<Option<Vec<Entry>>> {
+        let Some(first) = self.next.take().map(Ok).or_else(|| self.entries.next()).transpose()? else {
+            return Ok(None);
+        };
+
+        let mut versions = vec![first];
+        while let Some(entry) = self.entries.next().transpose()? {
+            if entry.get_key() != versions[0].get_key() {
+                self.next = Some(entry);
+                break;
+            }
+            versions.push(entry);
+        }
+
+        Ok(Some(versions))
+    }
+
+    /// Queues the versions of a key to write.
+    fn purge(&mut self, mut versions: Vec<Entry>) -> Result<()> {
+        let key = versions[0].get_key().to_vec();
+        let elsewhere = self.elsewhere(&key);
+
+        // The range tombstones covering the key join its versions, to hide the older ones, but are not written with them
+        let covering: BTreeSet<u64> = self.range_tombstones.iter().filter(|tombstone| tombstone.covers(&key)).map(Entry::get_sequence).collect();
+        for sequence in covering {
+            let position = versions.partition_point(|version| version.get_sequence() > sequence);
+            versions.insert(position, Entry::with_kind(&key, &[], EntryKind::RangeTombstone, sequence));
+        }
+
+        let mut last_stripe = None;
+        for (position, entry) in versions.iter().enumerate() {
+            // The versions of a key between two snapshots are only seen through the newest of them,
+            // the first snapshot that can see a version is the one it belongs to
+            let stripe = self.snapshots.partition_point(|&snapshot| snapshot < entry.get_sequence());
+            if last_stripe == Some(stripe) {
+                if !entry.is_range_tombstone() {
+                    self.stats.versions_purged += 1;
+                }
+                continue;
+            }
+            last_stripe = Some(stripe);
+            if entry.is_range_tombstone() {
+                continue;
+            }
+
+            let mut entry = entry.clone();
+            if entry.is_merge() {
+                let folded = if self.merger.has_operator() { self.fold_merge(&versions[position..])? } else { None };
+                match folded {
+                    Some(folded) => entry = folded,
+                    None => {
+                        // The operands still need versions of the key left out of the compaction
+                        let unfolded = versions[position..].iter().filter(|version| !version.is_range_tombstone()).cloned();
+                        let written = self.pending.len();
+                        self.pending.extend(unfolded);
+                        self.stats.written += (self.pending.len() - written) as u64;
+                        return Ok(());
+                    }
+                }
+            }
+
+            if entry.is_deleted() && stripe == 0 && !elsewhere {
+                self.stats.tombstones_purged += 1;
+                continue;
+            }
+
+            self.stats.written += 1;
+            self.pending.push_back(entry);
+        }
+
+        Ok(())
+    }
+}
+
+impl<I: Iterator<Item = Result<Entry>>> Iterator for Purge<'_, I> {
+    type Item = Result<Entry>;
+
+    /// Returns the next version to write.
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() {
+            let versions = match self.next_versions() {
+                Ok(versions) => versions?,
+                Err(err) => return Some(Err(err)),
+            };
+            if let Err(err) = self.purge(versions) {
+                return Some(Err(err));
+            }
+        }
+
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+/// Returns the smallest and the largest key of some SSTables, or `None` if they are all empty.
+pub fn key_range<'a>(sstables: impl Iterator<Item = &'a SSTable>) -> Option<(&'a [u8], &'a [u8])> {
+    sstables.filter(|sstable| !sstable.is_empty()).fold(None, |range, sstable| match range {
+        None => Some((sstable.get_first_key(), sstable.get_last_key())),
+        Some((first_key, last_key)) => Some((first_key.min(sstable.get_first_key()), last_key.max(sstable.get_last_key()))),
+    })
+}
+
+/// Returns the positions of the SSTables of a level whose keys overlap the given range, both ends inclusive.
+fn overlapping<'a>(levels: &'a [Vec<Arc<SSTable>>], level: usize, first_key: &'a [u8], last_key: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+    levels.get(level).into_iter().flat_map(move |sstables| sstables.iter().enumerate().filter(move |(_, sstable)| sstable.overlaps(first_key, last_key)).map(|(position, _)| position))
+}
+
+/// Returns the size in bytes of the SSTables of a level.
+fn level_size(sstables: &[Arc<SSTable>]) -> u64 {
+    sstables.iter().map(|sstable| sstable.get_size() as u64).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes an `SSTable` holding a single key whose value has the given size.
+    fn sstable(dir: &std::path::Path, id: u64, size: usize) -> Arc<SSTable> {
+        let entry = Entry::new(b"a", &vec![0; size], false, id + 1);
+        Arc::new(SSTable::create(&dir.join(format!("sst_{}.sst", id)), vec![entry], Vec::new(), &crate::lsm_tree::options::Options::default()).unwrap())
+    }
+
+    /// The key and the sequence number of each version written.
+    type Written = Vec<(Vec<u8>, u64)>;
+
+    fn purge(entries: Vec<Entry>, snapshots: Vec<u64>, range_tombstones: Vec<Entry>) -> (Written, Vec<Entry>, CompactionStats) {
+        let merger = Merger::default();
+        let mut purge = Purge::new(entries.into_iter().map(Ok), Vec::new(), snapshots, &merger).with_range_tombstones(range_tombstones);
+        let written = purge.by_ref().map(|entry| entry.map(|entry| (entry.get_key().to_vec(), entry.get_sequence()))).collect::<Result<_>>().unwrap();
+        (written, purge.get_range_tombstones().to_vec(), purge.get_stats())
+    }
+
+    #[test]
+    fn drops_hidden_versions_and_tombstones() {
+        let entries = vec![Entry::new(b"a", b"new", false, 3), Entry::new(b"a", b"old", false, 1), Entry::new(b"b", &[], true, 2), Entry::new(b"c", b"c", false, 4)];
+        let (written, _, stats) = purge(entries, Vec::new(), Vec::new());
+
+        assert_eq!(written, vec![(b"a".to_vec(), 3), (b"c".to_vec(), 4)]);
+        assert_eq!((stats.get_versions_purged(), stats.get_tombstones_purged(), stats.get_written()), (1, 1, 2));
+    }
+
+    #[test]
+    fn keeps_the_versions_seen_by_snapshots() {
+        let entries = vec![Entry::new(b"a", b"newest", false, 5), Entry::new(b"a", b"new", false, 3), Entry::new(b"a", b"old", false, 1), Entry::new(b"b", &[], true, 2), Entry::new(b"b", b"b", false, 1)];
+        let (written, _, stats) = purge(entries, vec![1], Vec::new());
+
+        // The snapshot at 1 sees the oldest version of each key, the tombstone of b hides it from the newer readers
+        assert_eq!(written, vec![(b"a".to_vec(), 5), (b"a".to_vec(), 1), (b"b".to_vec(), 2), (b"b".to_vec(), 1)]);
+        assert_eq!(stats.get_versions_purged(), 1);
+    }
+
+    #[test]
+    fn drops_the_versions_hidden_by_a_range_tombstone() {
+        let entries = vec![Entry::new(b"a", b"a", false, 1), Entry::new(b"b", b"b", false, 4), Entry::new(b"c", b"c", false, 2)];
+        let tombstone = Entry::with_kind(b"a", b"c", EntryKind::RangeTombstone, 3);
+
+        let (written, kept, stats) = purge(entries.clone(), Vec::new(), vec![tombstone.clone()]);
+        assert_eq!(written, vec![(b"b".to_vec(), 4), (b"c".to_vec(), 2)]);
+        assert!(kept.is_empty());
+        assert_eq!(stats.get_tombstones_purged(), 1);
+
+        // A snapshot older than the range tombstone still sees what it deletes
+        let (written, kept, _) = purge(entries, vec![2], vec![tombstone]);
+        assert_eq!(written, vec![(b"a".to_vec(), 1), (b"b".to_vec(), 4), (b"c".to_vec(), 2)]);
+        assert_eq!(kept.len(), 1);
+    }
+
+    #[test]
+    fn joins_the_parts_of_a_range_tombstone() {
+        let tombstone = Entry::with_kind(b"a", b"z", EntryKind::RangeTombstone, 3);
+        let parts = vec![tombstone.clip(None, Some(b"h")).unwrap(), tombstone.clip(Some(b"p"), None).unwrap(), tombstone.clip(Some(b"h"), Some(b"p")).unwrap()];
+        let (_, kept, _) = purge(vec![Entry::new(b"m", b"m", false, 1)], vec![2], parts);
+
+        assert_eq!(kept.len(), 1);
+        assert_eq!((kept[0].get_key(), kept[0].get_value(), kept[0].get_sequence()), (b"a".as_slice(), b"z".as_slice(), 3));
+        assert!(tombstone.clip(Some(b"z"), None).is_none());
+    }
+
+    #[test]
+    fn merges_sstables_of_similar_sizes() {
+        let dir = tempfile::tempdir().unwrap();
+        let strategy = SizeTieredCompaction { min_merge: 3, min_table_size: 0, ..Default::default() };
+
+        // The three SSTables of about 100 bytes are merged, the one of 1000 bytes is left alone
+        let levels = vec![vec![sstable(dir.path(), 0, 100), sstable(dir.path(), 1, 1000), sstable(dir.path(), 2, 110)], vec![sstable(dir.path(), 3, 120)]];
+        let compaction = strategy.pick(&levels).unwrap();
+        assert_eq!(compaction.get_inputs(), &[(0, 0), (0, 2), (1, 0)]);
+        assert_eq!(compaction.get_output_level(), 0);
+
+        // Two of them are not enough
+        assert!(strategy.pick(&levels[..1]).is_none());
+    }
+
+    #[test]
+    fn merges_levels_holding_too_many_sstables() {
+        let dir = tempfile::tempdir().unwrap();
+        let strategy = SimpleCompaction { max_tables: 2 };
+        let sstables: Vec<Arc<SSTable>> = (0..5).map(|id| sstable(dir.path(), id, 10)).collect();
+
+        // The first level holding more than two SSTables is merged whole into the next one
+        let levels = vec![sstables[..2].to_vec(), sstables[2..].to_vec()];
+        let compaction = strategy.pick(&levels).unwrap();
+        assert_eq!(compaction.get_inputs(), &[(1, 0), (1, 1), (1, 2)]);
+        assert_eq!(compaction.get_output_level(), 2);
+
+        assert!(strategy.pick(&[sstables[..2].to_vec(), sstables[2..4].to_vec()]).is_none());
+    }
+}
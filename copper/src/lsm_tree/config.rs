@@ -0,0 +1,77 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::Path,
+};
+
+use super::{
+    column::{Column, DataType},
+    error::{CopperError, Result},
+    manifest,
+};
+
+/// Version of the on-disk format written by this version of the library.
+/// Tables created before the version was recorded in `config.txt` are version 0,
+/// version 1 has the binary rows, the framed WAL and the block-based SSTables listed in a MANIFEST.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Reads the `config.txt` file of a table.
+///
+/// # Arguments
+///
+/// * `table_path` - A reference to the path of the table.
+///
+/// Returns the format version of the table and its columns.
+/// The first line holds the format version as `format|<version>`,
+/// every other line holds a column as `<name>|<data type character>`.
+/// Returns a `Corruption` error pointing at the first line that cannot be parsed.
+pub fn read_config(table_path: &Path) -> Result<(u32, Vec<Column>)> {
+    let config_path = table_path.join("config.txt");
+    let config = fs::read_to_string(&config_path)?;
+
+    let mut format = 0;
+    let mut columns = Vec::new();
+    let mut offset = 0;
+    for (i, line) in config.split_inclusive('\n').enumerate() {
+        let corruption = || CopperError::corruption(&config_path, offset as u64);
+        let content = line.trim_end_matches('\n');
+
+        // Tables written before the format was versioned have no format line
+        match content.strip_prefix("format|") {
+            Some(version) if i == 0 => format = version.parse().map_err(|_| corruption())?,
+            _ => {
+                let (name, data_type) = content.rsplit_once('|').ok_or_else(corruption)?;
+                let data_type = data_type.chars().next().ok_or_else(corruption)?;
+                columns.push(Column::new(name, DataType::from_char(data_type).map_err(|_| corruption())?));
+            }
+        }
+
+        offset += line.len();
+    }
+
+    Ok((format, columns))
+}
+
+/// Writes the `config.txt` file of a table with the current format version.
+///
+/// # Arguments
+///
+/// * `table_path` - A reference to the path of the table.
+/// * `columns` - A slice of `Column`s to record.
+///
+/// The file is first written and synced next to the config and then renamed over it,
+/// so a crash never leaves a partially written config behind, and the table directory is synced so the rename survives a crash.
+pub fn write_config(table_path: &Path, columns: &[Column]) -> Result<()> {
+    let mut config = format!("format|{}\n", FORMAT_VERSION);
+    for column in columns {
+        config.push_str(&format!("{}|{}\n", column.get_name(), column.get_data_type().get_char()));
+    }
+
+    let tmp_path = table_path.join("config.txt.tmp");
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(config.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(tmp_path, table_path.join("config.txt"))?;
+    manifest::sync_dir(table_path)?;
+    Ok(())
+}
@@ -0,0 +1,201 @@
+use std::ops::Bound;
+
+use super::{error::Result, iterator::MergeIterator, tree::LsmTree};
+
+/// `Cursor` struct is a position in the live keys of an `LsmTree`, that can move forward and backward.
+///
+/// It is useful for keyset pagination: the next page starts after the last key shown, the previous page ends before the first one.
+/// Moving in the same direction continues the underlying merge of the memtable and the SSTables,
+/// changing direction seeks again from the current key.
+pub struct Cursor<'a> {
+    tree: &'a LsmTree,
+    entries: Option<MergeIterator<'a>>,
+    current: Option<(Vec<u8>, Vec<u8>)>,
+    reverse: bool,
+}
+
+impl<'a> Cursor<'a> {
+    /// Creates a new `Cursor` over an `LsmTree`, not positioned yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `tree` - A reference to the `LsmTree` to go through.
+    pub fn new(tree: &'a LsmTree) -> Self {
+        Self { tree, entries: None, current: None, reverse: false }
+    }
+
+    /// Positions the cursor at the first key greater than or equal to the given key.
+    /// The cursor is not valid if there is no such key.
+    pub fn seek(&mut self, key: &[u8]) -> Result<()> {
+        self.position((Bound::Included(key), Bound::Unbounded), false)
+    }
+
+    /// Positions the cursor at the last key less than or equal to the given key.
+    /// The cursor is not valid if there is no such key.
+    pub fn seek_for_prev(&mut self, key: &[u8]) -> Result<()> {
+        self.position((Bound::Unbounded, Bound::Included(key)), true)
+    }
+
+    /// Positions the cursor at the first key of the tree.
+    /// The cursor is not valid if the tree is empty.
+    pub fn seek_to_first(&mut self) -> Result<()> {
+        self.position((Bound::Unbounded, Bound::Unbounded), false)
+    }
+
+    /// Positions the cursor at the last key of the tree.
+    /// The cursor is not valid if the tree is empty.
+    pub fn seek_to_last(&mut self) -> Result<()> {
+        self.position((Bound::Unbounded, Bound::Unbounded), true)
+    }
+
+    /// Moves the cursor to the next key.
+    /// The cursor is no longer valid once it moves past the last key, and stays so if it was not valid.
+    /// Unlike `Iterator::next`, it can be followed by `prev`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<()> {
+        match &self.current {
+            Some((key, _)) if self.reverse => {
+                let key = key.clone();
+                self.position((Bound::Excluded(&key), Bound::Unbounded), false)
+            }
+            Some(_) => self.advance(),
+            None => Ok(()),
+        }
+    }
+
+    /// Moves the cursor to the previous key.
+    /// The cursor is no longer valid once it moves before the first key, and stays so if it was not valid.
+    pub fn prev(&mut self) -> Result<()> {
+        match &self.current {
+            Some((key, _)) if !self.reverse => {
+                let key = key.clone();
+                self.position((Bound::Unbounded, Bound::Excluded(&key)), true)
+            }
+            Some(_) => self.advance(),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns whether the cursor is positioned at a key.
+    pub fn is_valid(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// Returns the key the cursor is positioned at, if it is valid.
+    pub fn key(&self) -> Option<&[u8]> {
+        self.current.as_ref().map(|(key, _)| key.as_slice())
+    }
+
+    /// Returns the row of the key the cursor is positioned at, if it is valid.
+    pub fn value(&self) -> Option<&[u8]> {
+        self.current.as_ref().map(|(_, value)| value.as_slice())
+    }
+
+    /// Starts a new merge over the given bounds, in key order or in reverse key order, and moves to its first key.
+    fn position(&mut self, range: (Bound<&[u8]>, Bound<&[u8]>), reverse: bool) -> Result<()> {
+        self.current = None;
+        self.entries = Some(if reverse { self.tree.scan_rev(range)? } else { self.tree.scan(range)? });
+        self.reverse = reverse;
+        self.advance()
+    }
+
+    /// Moves to the next key of the current merge.
+    fn advance(&mut self) -> Result<()> {
+        self.current = None;
+        if let Some(entry) = self.entries.as_mut().and_then(Iterator::next) {
+            self.current = Some(entry?);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lsm_tree::{
+        column::{Column, DataType},
+        options::Options,
+        tree::LsmTree,
+    };
+
+    /// Creates a table holding the keys b, d, f and h, the first two in an `SSTable` and the others in the memtable, and a deleted key e.
+    fn create(path: &std::path::Path) -> LsmTree {
+        let mut table = LsmTree::create(path.join("table").to_string_lossy().into_owned(), vec![Column::new("Name", DataType::Text)], Options { memtable_size: 4096, ..Options::default() }).unwrap();
+        for key in [b"b", b"d", b"e"] {
+            table.insert(key, &[key.to_vec()]).unwrap();
+        }
+        table.flush().unwrap();
+        for key in [b"f", b"h"] {
+            table.insert(key, &[key.to_vec()]).unwrap();
+        }
+        table.delete(b"e").unwrap();
+        table
+    }
+
+    #[test]
+    fn seeks_and_moves_both_ways() {
+        let dir = tempfile::tempdir().unwrap();
+        let table = create(dir.path());
+        let mut cursor = table.cursor();
+        assert!(!cursor.is_valid());
+
+        cursor.seek(b"c").unwrap();
+        assert_eq!(cursor.key(), Some(b"d".as_slice()));
+        assert_eq!(table.decode(cursor.value().unwrap()).unwrap()["Name"].get_text(), "d");
+        cursor.next().unwrap();
+        assert_eq!(cursor.key(), Some(b"f".as_slice()));
+        cursor.next().unwrap();
+        assert_eq!(cursor.key(), Some(b"h".as_slice()));
+
+        cursor.seek_for_prev(b"e").unwrap();
+        assert_eq!(cursor.key(), Some(b"d".as_slice()));
+        cursor.prev().unwrap();
+        assert_eq!(cursor.key(), Some(b"b".as_slice()));
+        cursor.prev().unwrap();
+        assert!(!cursor.is_valid());
+        cursor.next().unwrap();
+        assert!(!cursor.is_valid());
+
+        cursor.seek_to_first().unwrap();
+        assert_eq!(cursor.key(), Some(b"b".as_slice()));
+        cursor.seek_to_last().unwrap();
+        assert_eq!(cursor.key(), Some(b"h".as_slice()));
+    }
+
+    #[test]
+    fn changes_direction_from_the_current_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let table = create(dir.path());
+        let mut cursor = table.cursor();
+
+        cursor.seek(b"d").unwrap();
+        cursor.next().unwrap();
+        cursor.prev().unwrap();
+        assert_eq!(cursor.key(), Some(b"d".as_slice()));
+        cursor.prev().unwrap();
+        assert_eq!(cursor.key(), Some(b"b".as_slice()));
+        cursor.next().unwrap();
+        assert_eq!(cursor.key(), Some(b"d".as_slice()));
+        cursor.next().unwrap();
+        assert_eq!(cursor.key(), Some(b"f".as_slice()));
+    }
+
+    #[test]
+    fn is_not_valid_past_the_last_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let table = create(dir.path());
+        let mut cursor = table.cursor();
+
+        cursor.seek(b"i").unwrap();
+        assert!(!cursor.is_valid());
+        assert_eq!((cursor.key(), cursor.value()), (None, None));
+        cursor.prev().unwrap();
+        assert!(!cursor.is_valid());
+
+        cursor.seek(b"h").unwrap();
+        cursor.next().unwrap();
+        assert!(!cursor.is_valid());
+        cursor.seek_for_prev(b"a").unwrap();
+        assert!(!cursor.is_valid());
+    }
+}
@@ -0,0 +1,216 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use super::{
+    batch::WriteBatch,
+    column::Column,
+    error::Result,
+    options::Options,
+    snapshot::Snapshot,
+    transaction::Transaction,
+    tree::{LsmTree, Value},
+};
+
+/// `Db` struct is a handle to an open `LsmTree` that can be cloned and shared between threads.
+/// Every clone uses the same table, which is only opened once.
+/// Reads run concurrently, writes take turns: a write waits until the reads and the write in progress are done.
+/// The table is closed when the last clone is dropped.
+#[derive(Debug, Clone)]
+pub struct Db {
+    tree: Arc<RwLock<LsmTree>>,
+}
+
+impl Db {
+    /// Creates a new table at the given path and returns a handle to it, see `LsmTree::create`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A string that specifies the path of the database.
+    /// * `columns` - A vector of `Column`s that specifies the columns of the database.
+    /// * `options` - The `Options` used to open the database.
+    pub fn create(path: String, columns: Vec<Column>, options: Options) -> Result<Self> {
+        Ok(Self::new(LsmTree::create(path, columns, options)?))
+    }
+
+    /// Opens the existing table at the given path and returns a handle to it, see `LsmTree::open`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A string that specifies the path of the database.
+    /// * `columns` - A vector of `Column`s that the database is expected to have.
+    /// * `options` - The `Options` used to open the database.
+    pub fn open(path: String, columns: Vec<Column>, options: Options) -> Result<Self> {
+        Ok(Self::new(LsmTree::open(path, columns, options)?))
+    }
+
+    /// Opens the table at the given path, creating it if it does not exist yet, and returns a handle to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A string that specifies the path of the database.
+    /// * `columns` - A vector of `Column`s that specifies the columns of the database.
+    /// * `options` - The `Options` used to open the database.
+    pub fn open_or_create(path: String, columns: Vec<Column>, options: Options) -> Result<Self> {
+        Ok(Self::new(LsmTree::open_or_create(path, columns, options)?))
+    }
+
+    /// Wraps an open `LsmTree` in a handle.
+    ///
+    /// # Arguments
+    ///
+    /// * `tree` - The `LsmTree` to share.
+    pub fn new(tree: LsmTree) -> Self {
+        Self { tree: Arc::new(RwLock::new(tree)) }
+    }
+
+    /// Closes the table if this is its last handle, once its background work is stopped and its files are released.
+    /// Returns the handle back, leaving the table open, if clones of it are still in use.
+    pub fn close(self) -> std::result::Result<(), Self> {
+        match Arc::try_unwrap(self.tree) {
+            Ok(tree) => {
+                drop(tree);
+                Ok(())
+            }
+            Err(tree) => Err(Self { tree }),
+        }
+    }
+
+    /// Locks the table for reading, for as long as the returned guard lives.
+    /// Other readers go on meanwhile, writers wait.
+    /// This is the way to iterate over the table or to use a `Cursor`.
+    pub fn read(&self) -> RwLockReadGuard<'_, LsmTree> {
+        // A panic while the lock was held cannot leave the table half updated, every write goes to the WAL first
+        self.tree.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Locks the table for writing, for as long as the returned guard lives.
+    /// Waits until the reads and the write in progress are done, and blocks everyone else meanwhile.
+    /// This is the way to make several writes that no reader sees half done.
+    pub fn write(&self) -> RwLockWriteGuard<'_, LsmTree> {
+        self.tree.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Insert a key-value pair into the table, see `LsmTree::insert`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A byte slice that holds the key.
+    /// * `values` - A slice of byte vectors that holds the values of the columns, in order.
+    pub fn insert(&self, key: &[u8], values: &[Vec<u8>]) -> Result<()> {
+        self.write().insert(key, values)
+    }
+
+    /// Retrieve the row associated with a given key, see `LsmTree::get`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A byte slice that holds the key.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.read().get(key)
+    }
+
+    /// Takes a `Snapshot` of the table, which can be read from after the lock is released, see `LsmTree::snapshot`.
+    pub fn snapshot(&self) -> Snapshot {
+        self.read().snapshot()
+    }
+
+    /// Begins a `Transaction` on the table, see `LsmTree::begin_transaction`.
+    pub fn begin_transaction(&self) -> Transaction {
+        self.read().begin_transaction()
+    }
+
+    /// Commits a `Transaction`, see `LsmTree::commit`.
+    /// Returns a `Conflict` error if another thread wrote a key it uses first, the transaction can then be retried.
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The transaction to commit.
+    pub fn commit(&self, transaction: Transaction) -> Result<()> {
+        self.write().commit(transaction)
+    }
+
+    /// Merge an operand into the row of a key without reading it, see `LsmTree::merge`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A byte slice that holds the key.
+    /// * `operand` - A byte slice that holds the operand.
+    pub fn merge(&self, key: &[u8], operand: &[u8]) -> Result<()> {
+        self.write().merge(key, operand)
+    }
+
+    /// Delete a key from the table, see `LsmTree::delete`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A byte slice that holds the key.
+    pub fn delete(&self, key: &[u8]) -> Result<bool> {
+        self.write().delete(key)
+    }
+
+    /// Delete every key of a range from the table with a single range tombstone, see `LsmTree::delete_range`.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - A byte slice that holds the first key of the range, included.
+    /// * `end` - A byte slice that holds the end of the range, excluded.
+    pub fn delete_range(&self, start: &[u8], end: &[u8]) -> Result<()> {
+        self.write().delete_range(start, end)
+    }
+
+    /// Apply a `WriteBatch` to the table at once, see `LsmTree::write_batch`.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch` - The writes to apply, in order.
+    pub fn write_batch(&self, batch: &WriteBatch) -> Result<()> {
+        self.write().write_batch(batch)
+    }
+
+    /// Flush the memtable to an SSTable, see `LsmTree::flush`.
+    pub fn flush(&self) -> Result<()> {
+        self.write().flush()
+    }
+
+    /// Decodes a row of the table into its values, see `LsmTree::decode`.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - A byte slice that holds the row.
+    pub fn decode(&self, data: &[u8]) -> Result<HashMap<String, Value>> {
+        self.read().decode(data)
+    }
+}
+
+// `Db` is meant to be shared, this fails to build if the table ever holds something that cannot be
+const _: fn() = || {
+    fn is_send_sync<T: Send + Sync>() {}
+    is_send_sync::<Db>();
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsm_tree::column::DataType;
+
+    #[test]
+    fn closes_the_table_with_its_last_handle() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("table").to_string_lossy().into_owned();
+        let columns = vec![Column::new("Name", DataType::Text)];
+        let db = Db::create(path.clone(), columns.clone(), Options::default()).unwrap();
+        db.insert(b"a", &[b"a".to_vec()]).unwrap();
+
+        let clone = db.clone();
+        let db = db.close().unwrap_err();
+        assert!(clone.get(b"a").unwrap().is_some());
+        drop(clone);
+        db.close().unwrap();
+
+        // The table is no longer locked
+        let db = Db::open(path, columns, Options::default()).unwrap();
+        assert!(db.get(b"a").unwrap().is_some());
+    }
+}
@@ -1,23 +1,105 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, ops::Bound};
 
 /// `Entry` struct represents an entry in a database.
-/// It has a `key`, a `value`, and a `deleted` flag.
+/// It has a `key`, a `value`, a `kind` and the `sequence` number of the write that made it.
+/// Of two entries with the same key, the one with the highest sequence number is the newest.
+#[derive(Clone)]
 pub struct Entry {
     key: Vec<u8>,
     value: Vec<u8>,
-    deleted: bool,
+    kind: EntryKind,
+    sequence: u64,
+}
+
+/// `EntryKind` enum represents what an `Entry` holds, it is stored as a byte in the WAL and in SSTables.
+/// The byte 2 is left out, the WAL uses it in place of the kind to mark a batch of entries.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    /// The value of the key, an encoded row.
+    Value = 0,
+    /// A tombstone, the key is deleted.
+    Tombstone = 1,
+    /// Operands to fold with the older versions of the key by the `MergeOperator` of the table, see `merge::append_operand`.
+    Merge = 3,
+    /// A range tombstone, every key from the key of the entry, included, to its value, excluded, is deleted.
+    /// Range tombstones are kept apart from the other entries, in every `Memtable` and `SSTable`.
+    RangeTombstone = 4,
+}
+
+/// `Lookup` enum represents the result of looking up a key in a `Memtable` or an `SSTable`.
+/// A tombstone is reported as `Deleted`, so the lookup does not go on into older tables that may still hold the key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lookup {
+    /// The key holds a value, written by the write with the given sequence number.
+    Found { value: Vec<u8>, sequence: u64 },
+    /// The key was deleted by the write with the given sequence number.
+    Deleted { sequence: u64 },
+    /// The key holds merge operands, written by the write with the given sequence number,
+    /// its value is found by folding them with its older versions.
+    Merge { sequence: u64 },
+    /// The key is not in the table.
+    Absent,
+}
+
+impl Lookup {
+    /// Returns the sequence number of the write that was found, or `None` if the key is absent.
+    pub fn get_sequence(&self) -> Option<u64> {
+        match self {
+            Lookup::Found { sequence, .. } | Lookup::Deleted { sequence } | Lookup::Merge { sequence } => Some(*sequence),
+            Lookup::Absent => None,
+        }
+    }
+
+    /// Returns the lookup as seen through the range tombstones covering the key:
+    /// `Deleted` if the newest of them is newer than the version found, the lookup itself otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `tombstone` - The sequence number of the newest range tombstone covering the key, if any, see `newest_covering`.
+    pub fn covered_by(self, tombstone: Option<u64>) -> Self {
+        match tombstone {
+            Some(sequence) if tombstone > self.get_sequence() => Lookup::Deleted { sequence },
+            _ => self,
+        }
+    }
+}
+
+impl From<Option<&Entry>> for Lookup {
+    /// Converts the entry found for a key, if any, into a `Lookup`.
+    fn from(entry: Option<&Entry>) -> Self {
+        match entry.map(|entry| (entry, entry.get_kind())) {
+            Some((entry, EntryKind::Value)) => Lookup::Found { value: entry.get_value().to_vec(), sequence: entry.get_sequence() },
+            Some((entry, EntryKind::Tombstone | EntryKind::RangeTombstone)) => Lookup::Deleted { sequence: entry.get_sequence() },
+            Some((entry, EntryKind::Merge)) => Lookup::Merge { sequence: entry.get_sequence() },
+            None => Lookup::Absent,
+        }
+    }
 }
 
 impl Entry {
-    /// Creates a new `Entry` with the given `key`, `value`, and `deleted` flag.
+    /// Creates a new `Entry` with the given `key`, `value`, `deleted` flag and `sequence` number.
     ///
     /// # Arguments
     ///
     /// * `key` - A byte slice that holds the key of the entry.
     /// * `value` - A byte slice that holds the value of the entry.
     /// * `deleted` - A boolean indicating whether the entry is deleted.
-    pub fn new(key: &[u8], value: &[u8], deleted: bool) -> Self {
-        Self { key: key.to_vec(), value: value.to_vec(), deleted }
+    /// * `sequence` - The sequence number of the write, 0 for entries written before writes were numbered.
+    pub fn new(key: &[u8], value: &[u8], deleted: bool, sequence: u64) -> Self {
+        Self::with_kind(key, value, if deleted { EntryKind::Tombstone } else { EntryKind::Value }, sequence)
+    }
+
+    /// Creates a new `Entry` of any `EntryKind`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A byte slice that holds the key of the entry.
+    /// * `value` - A byte slice that holds the value of the entry.
+    /// * `kind` - What the entry holds.
+    /// * `sequence` - The sequence number of the write, 0 for entries written before writes were numbered.
+    pub fn with_kind(key: &[u8], value: &[u8], kind: EntryKind, sequence: u64) -> Self {
+        Self { key: key.to_vec(), value: value.to_vec(), kind, sequence }
     }
 
     /// Returns the key of the `Entry`.
@@ -37,13 +119,98 @@ impl Entry {
 
     /// Returns whether the `Entry` is deleted.
     pub fn is_deleted(&self) -> bool {
-        self.deleted
+        self.kind == EntryKind::Tombstone
+    }
+
+    /// Returns whether the `Entry` holds merge operands.
+    pub fn is_merge(&self) -> bool {
+        self.kind == EntryKind::Merge
+    }
+
+    /// Returns whether the `Entry` is a range tombstone.
+    pub fn is_range_tombstone(&self) -> bool {
+        self.kind == EntryKind::RangeTombstone
+    }
+
+    /// Returns whether the `Entry` is a range tombstone deleting the given key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A byte slice that holds the key.
+    pub fn covers(&self, key: &[u8]) -> bool {
+        self.is_range_tombstone() && self.key.as_slice() <= key && key < self.value.as_slice()
+    }
+
+    /// Returns the part of a range tombstone that deletes keys from a lower bound, included, to an upper bound, excluded,
+    /// or `None` if it deletes none of them.
+    ///
+    /// # Arguments
+    ///
+    /// * `lower` - A byte slice that holds the lower bound, or `None` for no lower bound.
+    /// * `upper` - A byte slice that holds the upper bound, or `None` for no upper bound.
+    pub fn clip(&self, lower: Option<&[u8]>, upper: Option<&[u8]>) -> Option<Entry> {
+        let start = lower.map_or(self.key.as_slice(), |lower| lower.max(self.key.as_slice()));
+        let end = upper.map_or(self.value.as_slice(), |upper| upper.min(self.value.as_slice()));
+        (start < end).then(|| Entry::with_kind(start, end, self.kind, self.sequence))
+    }
+
+    /// Returns what the `Entry` holds.
+    pub fn get_kind(&self) -> EntryKind {
+        self.kind
+    }
+
+    /// Returns the sequence number of the `Entry`.
+    pub fn get_sequence(&self) -> u64 {
+        self.sequence
+    }
+}
+
+impl EntryKind {
+    /// Returns the `EntryKind` stored as the given byte, or `None` if the byte is not one.
+    ///
+    /// # Arguments
+    ///
+    /// * `byte` - The byte read from the WAL or an SSTable.
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(EntryKind::Value),
+            1 => Some(EntryKind::Tombstone),
+            3 => Some(EntryKind::Merge),
+            4 => Some(EntryKind::RangeTombstone),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the sequence number of the newest range tombstone deleting a key, ignoring the ones newer than a sequence number,
+/// or `None` if no range tombstone deletes it.
+///
+/// # Arguments
+///
+/// * `tombstones` - The range tombstones to look through.
+/// * `key` - A byte slice that holds the key.
+/// * `sequence` - The sequence number of the last write to see.
+pub fn newest_covering(tombstones: &[Entry], key: &[u8], sequence: u64) -> Option<u64> {
+    tombstones.iter().filter(|tombstone| tombstone.get_sequence() <= sequence && tombstone.covers(key)).map(Entry::get_sequence).max()
+}
+
+/// Returns whether no key lies between two bounds, which is the case when the lower bound is past the upper bound.
+///
+/// # Arguments
+///
+/// * `start` - The lower bound of the keys.
+/// * `end` - The upper bound of the keys.
+pub fn is_empty_range(start: Bound<&[u8]>, end: Bound<&[u8]>) -> bool {
+    match (start, end) {
+        (Bound::Included(start), Bound::Included(end)) => start > end,
+        (Bound::Included(start) | Bound::Excluded(start), Bound::Included(end) | Bound::Excluded(end)) => start >= end,
+        _ => false,
     }
 }
 
 impl Debug for Entry {
     /// Formats the `Entry` for printing.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Entry(key: {:?}, value: {:?}, deleted: {:?})", String::from_utf8_lossy(&self.key), String::from_utf8_lossy(&self.value), self.deleted)
+        write!(f, "Entry(key: {:?}, value: {:?}, kind: {:?}, sequence: {})", String::from_utf8_lossy(&self.key), String::from_utf8_lossy(&self.value), self.kind, self.sequence)
     }
 }
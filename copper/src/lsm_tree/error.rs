@@ -0,0 +1,89 @@
+use std::{
+    fmt::{Display, Formatter},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use super::column::Column;
+
+/// `CopperError` enum represents every error returned by the `lsm_tree` module.
+#[derive(Debug)]
+pub enum CopperError {
+    /// An operation on the file system failed.
+    Io(std::io::Error),
+    /// A file of the table holds data that cannot be read back, starting at the given byte offset.
+    Corruption { file: PathBuf, offset: u64 },
+    /// The table was opened with other columns than the ones it was created with.
+    SchemaMismatch { expected: Vec<Column>, found: Vec<Column> },
+    /// A value given to or read from the table does not respect its columns.
+    InvalidValue(String),
+    /// The table does not exist.
+    NotFound(String),
+    /// The table already exists.
+    AlreadyExists(String),
+    /// The table is already open in another process, for writing, or for reading when opening it for writing.
+    AlreadyLocked(String),
+    /// The table was opened read-only and cannot be written to.
+    ReadOnly,
+    /// A transaction could not commit because someone else wrote the given key since it began,
+    /// nothing was written and the transaction can be retried from the start.
+    Conflict(Vec<u8>),
+    /// The table holds or is given merge operands but was opened without a `MergeOperator` in its `Options`.
+    NoMergeOperator,
+    /// The table was written with a format newer than the one this version of the library understands.
+    UnsupportedFormat(u32),
+    /// A background flush or compaction failed with the given error, the table must be opened again to recover.
+    Background(Arc<CopperError>),
+}
+
+/// `Result` type returned by the `lsm_tree` module.
+pub type Result<T> = std::result::Result<T, CopperError>;
+
+impl CopperError {
+    /// Creates a `Corruption` error for the given file and offset.
+    pub fn corruption(file: impl Into<PathBuf>, offset: u64) -> Self {
+        CopperError::Corruption { file: file.into(), offset }
+    }
+
+    /// Returns true if the operation that failed can succeed when tried again, which is the case of a `Conflict`.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, CopperError::Conflict(_))
+    }
+}
+
+impl Display for CopperError {
+    /// Formats the `CopperError` for printing.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CopperError::Io(err) => write!(f, "I/O error: {}", err),
+            CopperError::Corruption { file, offset } => write!(f, "Corrupted data in {} at offset {}", file.display(), offset),
+            CopperError::SchemaMismatch { expected, found } => write!(f, "Schema mismatch: expected columns {:?}, found {:?}", expected, found),
+            CopperError::InvalidValue(message) => write!(f, "Invalid value: {}", message),
+            CopperError::NotFound(table) => write!(f, "Table {} does not exist", table),
+            CopperError::AlreadyExists(table) => write!(f, "Table {} already exists", table),
+            CopperError::AlreadyLocked(table) => write!(f, "Table {} is already open in another process", table),
+            CopperError::ReadOnly => write!(f, "Table is open read-only"),
+            CopperError::Conflict(key) => write!(f, "Transaction conflicts with a write to key {}", String::from_utf8_lossy(key)),
+            CopperError::NoMergeOperator => write!(f, "Table has no merge operator"),
+            CopperError::UnsupportedFormat(format) => write!(f, "Unsupported table format {}", format),
+            CopperError::Background(err) => write!(f, "Background work failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for CopperError {
+    /// Returns the underlying I/O error, or the error of the background work, if any.
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CopperError::Io(err) => Some(err),
+            CopperError::Background(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for CopperError {
+    fn from(err: std::io::Error) -> CopperError {
+        CopperError::Io(err)
+    }
+}
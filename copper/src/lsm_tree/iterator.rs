@@ -0,0 +1,202 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    sync::Arc,
+};
+
+use super::{
+    entry::{self, Entry},
+    error::{CopperError, Result},
+    merge::Merger,
+};
+
+/// `MergeIterator` struct merges sorted sources of entries, such as a `Memtable` and `SSTable`s, into one sorted view.
+/// The sources and the view are either all in key order or all in reverse key order.
+///
+/// It keeps a heap holding the next entry of every source, so its memory is bounded by the number of sources.
+/// Each key is returned once, with its newest version, and keys whose newest version is a tombstone are skipped.
+/// The newest version of a key is the one with the highest sequence number,
+/// on a tie, which only happens for entries written before writes were numbered, the one from the first source.
+/// A newest version holding merge operands is folded with the older ones by the `Merger` of the table.
+/// A range tombstone covering a key is one more version of it, a tombstone with the sequence number of the range tombstone.
+pub struct MergeIterator<'a> {
+    sources: Vec<Box<dyn Iterator<Item = Result<Entry>> + 'a>>,
+    heap: BinaryHeap<HeapEntry>,
+    reverse: bool,
+    merger: Option<Arc<Merger>>,
+    range_tombstones: Vec<Entry>,
+    all_versions: bool,
+    started: bool,
+    failed: bool,
+}
+
+/// `HeapEntry` struct is the next entry of a source of a `MergeIterator`.
+/// Its order makes the `BinaryHeap` pop the smallest key first, or the largest one in reverse,
+/// and for a key its newest version first.
+struct HeapEntry {
+    entry: Entry,
+    source: usize,
+    reverse: bool,
+}
+
+impl<'a> MergeIterator<'a> {
+    /// Creates a new `MergeIterator`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sources` - The sources to merge, each sorted by key, the newest source first.
+    ///   A source can hold several versions of a key, the newest first, or the oldest first in reverse key order.
+    /// * `reverse` - Whether the sources, and the merged view, are in reverse key order.
+    pub fn new(sources: Vec<Box<dyn Iterator<Item = Result<Entry>> + 'a>>, reverse: bool) -> Self {
+        Self { sources, heap: BinaryHeap::new(), reverse, merger: None, range_tombstones: Vec::new(), all_versions: false, started: false, failed: false }
+    }
+
+    /// Makes the iterator skip the versions of keys hidden by range tombstones.
+    /// They are not applied with `with_all_versions`, a compaction goes through them apart.
+    ///
+    /// # Arguments
+    ///
+    /// * `range_tombstones` - The range tombstones of the sources, newer ones than the sources are meant to show left out.
+    pub fn with_range_tombstones(mut self, range_tombstones: Vec<Entry>) -> Self {
+        self.range_tombstones = range_tombstones;
+        self
+    }
+
+    /// Makes the iterator fold merge operands with the `Merger` of the table,
+    /// without it a key whose newest version holds operands returns a `NoMergeOperator` error.
+    ///
+    /// # Arguments
+    ///
+    /// * `merger` - The `Merger` of the table.
+    pub fn with_merger(mut self, merger: Arc<Merger>) -> Self {
+        self.merger = Some(merger);
+        self
+    }
+
+    /// Makes `next_entry` return every version of every key, tombstones included, the newest version of a key first,
+    /// so a compaction can decide which ones it keeps.
+    pub fn with_all_versions(mut self) -> Self {
+        self.all_versions = true;
+        self
+    }
+
+    /// Returns the newest version of the next live key, with its sequence number.
+    /// With `with_all_versions`, returns the next version of a key instead.
+    pub fn next_entry(&mut self) -> Option<Result<Entry>> {
+        if self.failed {
+            return None;
+        }
+
+        // Fill the heap with the first entry of every source
+        if !self.started {
+            self.started = true;
+            for source in 0..self.sources.len() {
+                if let Err(err) = self.advance(source) {
+                    return Some(Err(err));
+                }
+            }
+        }
+
+        while let Some(HeapEntry { entry, source, .. }) = self.heap.pop() {
+            if let Err(err) = self.advance(source) {
+                return Some(Err(err));
+            }
+            if self.all_versions {
+                return Some(Ok(entry));
+            }
+
+            // Gather the other versions of the key and sort them the newest first,
+            // a source read in reverse key order gives the versions of a key the oldest first
+            let mut versions = vec![(entry, source)];
+            while self.heap.peek().is_some_and(|next| next.entry.get_key() == versions[0].0.get_key()) {
+                let Some(other) = self.heap.pop() else {
+                    break;
+                };
+                if let Err(err) = self.advance(other.source) {
+                    return Some(Err(err));
+                }
+                versions.push((other.entry, other.source));
+            }
+            if let Some(sequence) = entry::newest_covering(&self.range_tombstones, versions[0].0.get_key(), u64::MAX) {
+                let tombstone = Entry::new(versions[0].0.get_key(), &[], true, sequence);
+                versions.push((tombstone, self.sources.len()));
+            }
+            versions.sort_by_key(|(entry, source)| Reverse((entry.get_sequence(), Reverse(*source))));
+            let mut versions: Vec<Entry> = versions.into_iter().map(|(entry, _)| entry).collect();
+
+            // Keep the newest version, or fold it with the older ones if it holds merge operands
+            let entry = if versions[0].is_merge() {
+                match self.fold_versions(&versions) {
+                    Ok(folded) => folded,
+                    Err(err) => {
+                        self.failed = true;
+                        return Some(Err(err));
+                    }
+                }
+            } else {
+                versions.swap_remove(0)
+            };
+
+            if !entry.is_deleted() {
+                return Some(Ok(entry));
+            }
+        }
+
+        None
+    }
+
+    /// Folds every version of a key, the newest first, into a value or a tombstone with the `Merger` of the table.
+    fn fold_versions(&self, versions: &[Entry]) -> Result<Entry> {
+        let merger = self.merger.as_ref().ok_or(CopperError::NoMergeOperator)?;
+        // The versions are complete, so they always fold
+        Ok(merger.fold(versions, true)?.unwrap_or_else(|| versions[0].clone()))
+    }
+
+    /// Pushes the next entry of a source on the heap.
+    /// An error stops the iterator.
+    fn advance(&mut self, source: usize) -> Result<()> {
+        match self.sources[source].next() {
+            Some(Ok(entry)) => self.heap.push(HeapEntry { entry, source, reverse: self.reverse }),
+            Some(Err(err)) => {
+                self.failed = true;
+                return Err(err);
+            }
+            None => {}
+        }
+
+        Ok(())
+    }
+}
+
+impl Iterator for MergeIterator<'_> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    /// Returns the next live key and its row, in key order or in reverse key order.
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().map(|entry| entry.map(|entry| (entry.get_key().to_vec(), entry.get_value().to_vec())))
+    }
+}
+
+impl Ord for HeapEntry {
+    /// Orders by key, then by sequence number and source, so the `BinaryHeap` pops the smallest key first,
+    /// or the largest one in reverse, and for a key the highest sequence number, then the first source.
+    fn cmp(&self, other: &Self) -> Ordering {
+        let keys = other.entry.get_key().cmp(self.entry.get_key());
+        let keys = if self.reverse { keys.reverse() } else { keys };
+        keys.then(self.entry.get_sequence().cmp(&other.entry.get_sequence())).then(other.source.cmp(&self.source))
+    }
+}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for HeapEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HeapEntry {}
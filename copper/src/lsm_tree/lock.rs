@@ -0,0 +1,101 @@
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io::ErrorKind,
+    path::Path,
+};
+
+use super::error::{CopperError, Result};
+
+/// `LockFile` struct holds the `LOCK` file of a table, locked for as long as the table is open.
+///
+/// A table opened for writing holds an exclusive lock, so no other process can open it at the same time.
+/// A table opened read-only holds a shared lock, so several processes can read it but none can write to it meanwhile.
+/// The lock is released when the `LockFile` is dropped, or by the system if the process dies.
+#[derive(Debug)]
+pub struct LockFile {
+    // Kept open to hold the lock
+    _file: File,
+}
+
+impl LockFile {
+    /// Locks a table for writing.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_path` - A reference to the path of the table.
+    ///
+    /// Returns an `AlreadyLocked` error if another process has the table open.
+    pub fn exclusive(table_path: &Path) -> Result<Self> {
+        let file = Self::open(table_path, true)?;
+        Self::check(table_path, file.try_lock())?;
+        Ok(Self { _file: file })
+    }
+
+    /// Locks a table for reading.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_path` - A reference to the path of the table.
+    ///
+    /// The `LOCK` file is only opened for reading, so a table can be opened read-only from a read-only folder or by a user that cannot write to it.
+    /// Returns an `AlreadyLocked` error if another process has the table open for writing.
+    pub fn shared(table_path: &Path) -> Result<Self> {
+        let file = Self::open(table_path, false)?;
+        Self::check(table_path, file.try_lock_shared())?;
+        Ok(Self { _file: file })
+    }
+
+    /// Opens the `LOCK` file of a table, creating it if needed, tables created by older versions have none.
+    /// Without `write`, an existing `LOCK` file is opened for reading only.
+    fn open(table_path: &Path, write: bool) -> Result<File> {
+        let lock_path = table_path.join("LOCK");
+        if !write {
+            match File::open(&lock_path) {
+                Ok(file) => return Ok(file),
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(OpenOptions::new().read(true).write(true).create(true).truncate(false).open(lock_path)?)
+    }
+
+    /// Turns the outcome of an attempt to take the lock into a `Result`.
+    fn check(table_path: &Path, locked: std::result::Result<(), TryLockError>) -> Result<()> {
+        match locked {
+            Ok(()) => Ok(()),
+            Err(TryLockError::WouldBlock) => Err(CopperError::AlreadyLocked(table_path.display().to_string())),
+            Err(TryLockError::Error(err)) => Err(CopperError::Io(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locks_a_table_for_one_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock = LockFile::exclusive(dir.path()).unwrap();
+        assert!(matches!(LockFile::exclusive(dir.path()), Err(CopperError::AlreadyLocked(_))));
+        assert!(matches!(LockFile::shared(dir.path()), Err(CopperError::AlreadyLocked(_))));
+
+        drop(lock);
+        assert!(LockFile::exclusive(dir.path()).is_ok());
+    }
+
+    #[test]
+    fn locks_a_table_for_several_readers() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // The first reader of a table without a `LOCK` file creates it
+        let first = LockFile::shared(dir.path()).unwrap();
+        let second = LockFile::shared(dir.path()).unwrap();
+        assert!(dir.path().join("LOCK").exists());
+        assert!(matches!(LockFile::exclusive(dir.path()), Err(CopperError::AlreadyLocked(_))));
+
+        drop((first, second));
+        assert!(LockFile::exclusive(dir.path()).is_ok());
+    }
+}
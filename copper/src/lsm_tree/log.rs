@@ -3,19 +3,25 @@ use std::fs::OpenOptions;
 use std::io::Write;
 use std::path::Path;
 
-/// `Entry` struct represents an entry in a database.
-/// It has a `key`, a `value`, and a `deleted` flag.
+use super::error::Result;
+
+/// `Operation` enum represents an operation written to the log of a table.
 pub enum Operation {
     Deletion(Vec<u8>),
+    RangeDeletion(Vec<u8>, Vec<u8>),
     Insertion(Vec<u8>, Vec<u8>),
+    Merge(Vec<u8>, Vec<u8>),
+    Batch(usize, usize),
     Flush,
     Creation,
     Load,
     Get(Vec<u8>),
     GetRange,
-    Compact,
+    Compact(u64, u64),
     Clear,
     Decode(Vec<u8>),
+    Migration(u32, usize),
+    Recovery(usize, usize),
 }
 
 /// Writes a log of an operation to a file.
@@ -28,58 +34,84 @@ pub enum Operation {
 /// This function creates a file named "log.txt" in the specified directory if it does not exist,
 /// and appends a log of the operation to the file.
 /// The log includes the date and time of the operation and the details of the operation.
-pub fn write_log(path: &Path, operation: Operation) {
+/// The line is built in memory first and written with a single call.
+pub fn write_log(path: &Path, operation: Operation) -> Result<()> {
     // Create the path
     let path = path.join("log.txt");
 
     // Open a file, if it already exists, append
-    let mut file = OpenOptions::new().create(true).append(true).open(path).unwrap();
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut line = Vec::new();
 
     // Write the date and time of the operation
     let now = Local::now();
-    file.write_all(b"[").unwrap();
+    line.extend_from_slice(b"[");
     let date_time = now.format("%Y-%m-%d %H:%M:%S").to_string();
-    file.write_all(date_time.as_bytes()).unwrap();
+    line.extend_from_slice(date_time.as_bytes());
 
     // Write the operation
     match operation {
         Operation::Deletion(key) => {
-            file.write_all(b"] Deletion : ").unwrap();
-            file.write_all(&key).unwrap();
+            line.extend_from_slice(b"] Deletion : ");
+            line.extend_from_slice(&key);
+        }
+        Operation::RangeDeletion(start, end) => {
+            line.extend_from_slice(b"] Range deletion : ");
+            line.extend_from_slice(&start);
+            line.extend_from_slice(b" : ");
+            line.extend_from_slice(&end);
         }
         Operation::Insertion(key, value) => {
-            file.write_all(b"] Insertion : ").unwrap();
-            file.write_all(&key).unwrap();
-            file.write_all(b" : ").unwrap();
-            file.write_all(&value).unwrap();
+            line.extend_from_slice(b"] Insertion : ");
+            line.extend_from_slice(&key);
+            line.extend_from_slice(b" : ");
+            line.extend_from_slice(&value);
+        }
+        Operation::Merge(key, operand) => {
+            line.extend_from_slice(b"] Merge : ");
+            line.extend_from_slice(&key);
+            line.extend_from_slice(b" : ");
+            line.extend_from_slice(&operand);
+        }
+        Operation::Batch(writes, entries) => {
+            line.extend_from_slice(format!("] Batch of {} writes applied, {} entries written", writes, entries).as_bytes());
         }
         Operation::Flush => {
-            file.write_all(b"] Memtable flushed").unwrap();
+            line.extend_from_slice(b"] Memtable flushed");
         }
         Operation::Creation => {
-            file.write_all(b"] Table created").unwrap();
+            line.extend_from_slice(b"] Table created");
         }
         Operation::Load => {
-            file.write_all(b"] Table loaded").unwrap();
+            line.extend_from_slice(b"] Table loaded");
         }
         Operation::Get(key) => {
-            file.write_all(b"] Get : ").unwrap();
-            file.write_all(&key).unwrap();
+            line.extend_from_slice(b"] Get : ");
+            line.extend_from_slice(&key);
         }
-        Operation::Compact => {
-            file.write_all(b"] Levels compacted").unwrap();
+        Operation::Compact(tombstones, versions) => {
+            line.extend_from_slice(format!("] Levels compacted, {} tombstones and {} older versions purged", tombstones, versions).as_bytes());
         }
         Operation::GetRange => {
-            file.write_all(b"] Get range with predicate").unwrap();
+            line.extend_from_slice(b"] Get range with predicate");
         }
         Operation::Clear => {
-            file.write_all(b"] Table cleared").unwrap();
+            line.extend_from_slice(b"] Table cleared");
         }
         Operation::Decode(key) => {
-            file.write_all(b"] Decoding : ").unwrap();
-            file.write_all(&key).unwrap();
+            line.extend_from_slice(b"] Decoding : ");
+            line.extend_from_slice(&key);
+        }
+        Operation::Migration(format, dropped) => {
+            line.extend_from_slice(format!("] Table migrated from format {}, {} unreadable rows dropped", format, dropped).as_bytes());
+        }
+        Operation::Recovery(recovered, discarded) => {
+            line.extend_from_slice(format!("] WAL recovered, {} records replayed, {} discarded", recovered, discarded).as_bytes());
         }
     }
 
-    file.write_all(b"\n").unwrap();
+    line.extend_from_slice(b"\n");
+
+    file.write_all(&line)?;
+    Ok(())
 }
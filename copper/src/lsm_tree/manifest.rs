@@ -0,0 +1,195 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Write},
+    path::Path,
+};
+
+use super::error::{CopperError, Result};
+
+/// Size of the header of a MANIFEST record: the payload length and its CRC32, both big endian `u32`.
+const HEADER_SIZE: usize = 8;
+
+/// `VersionEdit` enum represents a change to the set of SSTable files of a table.
+/// Files are identified by their level and their id, the file of an SSTable is `ssts/sst_<level>_<id>.sst`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionEdit {
+    /// The file was added in front of its level.
+    Add { level: usize, id: u64 },
+    /// The file was removed from its level.
+    Remove { level: usize, id: u64 },
+    /// Every file was removed by `LsmTree::clear`, along with every write older than the clear, given by its sequence number.
+    Truncate { sequence: u64 },
+}
+
+/// `Manifest` struct is an open handle on the MANIFEST of a table,
+/// the log of the `VersionEdit`s that made its current set of SSTable files.
+///
+/// A file only belongs to the table once an edit adding it is synced to the MANIFEST,
+/// so a crash while an SSTable is written, flushed or compacted leaves the table as it was before.
+pub struct Manifest {
+    file: File,
+}
+
+impl Manifest {
+    /// Creates the MANIFEST of a table, replacing the current one.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_path` - A reference to the path of the table.
+    /// * `levels` - The ids of the files of each level, the newest first.
+    /// * `truncated` - The sequence number of the last `VersionEdit::Truncate`, 0 if there was none.
+    ///
+    /// The new MANIFEST holds a single record keeping the truncation, then adding every file.
+    /// It is written next to the current one and renamed over it once synced,
+    /// so a crash leaves either the old or the new MANIFEST, and the table directory is synced so the rename itself survives a crash.
+    pub fn create(table_path: &Path, levels: &[Vec<u64>], truncated: u64) -> Result<Self> {
+        let mut edits = Vec::new();
+        if truncated > 0 {
+            edits.push(VersionEdit::Truncate { sequence: truncated });
+        }
+        for (level, ids) in levels.iter().enumerate() {
+            // Edits add files in front of their level, so the oldest file is added first
+            edits.extend(ids.iter().rev().map(|&id| VersionEdit::Add { level, id }));
+        }
+
+        let tmp_path = table_path.join("MANIFEST.tmp");
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(&encode_record(&edits))?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, table_path.join("MANIFEST"))?;
+        sync_dir(table_path)?;
+
+        let file = OpenOptions::new().append(true).open(table_path.join("MANIFEST"))?;
+        Ok(Self { file })
+    }
+
+    /// Appends edits to the MANIFEST as one record and syncs it.
+    ///
+    /// # Arguments
+    ///
+    /// * `edits` - A slice of the `VersionEdit`s to record.
+    ///
+    /// The edits of a record are applied together when the MANIFEST is replayed, or not at all if the record is torn.
+    pub fn append(&mut self, edits: &[VersionEdit]) -> Result<()> {
+        self.file.write_all(&encode_record(edits))?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+/// Syncs a directory, so the files created, renamed or removed in it survive a crash.
+/// Syncing a file only makes its content durable, not its entry in the directory.
+///
+/// # Arguments
+///
+/// * `path` - A reference to the path of the directory.
+pub fn sync_dir(path: &Path) -> Result<()> {
+    // Directories cannot be opened as files on every platform, there the rename is left to the file system
+    #[cfg(unix)]
+    File::open(path)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Replays the MANIFEST of a table.
+///
+/// # Arguments
+///
+/// * `table_path` - A reference to the path of the table.
+///
+/// Returns the ids of the files of each level, the newest first, and the sequence number of the last `VersionEdit::Truncate`,
+/// 0 if there was none, or `None` if the table has no MANIFEST.
+/// A record cut short by a crash can only be the last one, it was never acknowledged and is ignored.
+/// Returns a `Corruption` error if a complete record has a bad checksum or cannot be decoded.
+pub fn recover(table_path: &Path) -> Result<Option<(Vec<Vec<u64>>, u64)>> {
+    let manifest_path = table_path.join("MANIFEST");
+    let data = match fs::read(&manifest_path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut levels: Vec<Vec<u64>> = Vec::new();
+    let mut truncated = 0;
+    let mut offset = 0;
+    while offset < data.len() {
+        // A record that does not fit in the file is a torn write
+        let Some(header) = data.get(offset..offset + HEADER_SIZE) else {
+            break;
+        };
+        let len = u32::from_be_bytes(header[0..4].try_into().unwrap_or_default()) as usize;
+        let crc = u32::from_be_bytes(header[4..8].try_into().unwrap_or_default());
+        let Some(payload) = data.get(offset + HEADER_SIZE..offset + HEADER_SIZE + len) else {
+            break;
+        };
+
+        let edits = decode_payload(payload).filter(|_| crc32fast::hash(payload) == crc).ok_or_else(|| CopperError::corruption(&manifest_path, offset as u64))?;
+        for edit in edits {
+            match edit {
+                VersionEdit::Add { level, id } => {
+                    while levels.len() <= level {
+                        levels.push(Vec::new());
+                    }
+                    levels[level].insert(0, id);
+                }
+                VersionEdit::Remove { level, id } => {
+                    if let Some(ids) = levels.get_mut(level) {
+                        ids.retain(|&file_id| file_id != id);
+                    }
+                }
+                VersionEdit::Truncate { sequence } => {
+                    levels.clear();
+                    truncated = sequence;
+                }
+            }
+        }
+
+        offset += HEADER_SIZE + len;
+    }
+
+    Ok(Some((levels, truncated)))
+}
+
+/// Frames edits as one record: the payload length and its CRC32, followed by the payload.
+/// The payload holds the number of edits, then for each edit its kind (1 to add, 2 to remove), its level and its id,
+/// or its kind (3 to truncate), a level of 0 and its sequence number.
+fn encode_record(edits: &[VersionEdit]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(4 + edits.len() * 13);
+    payload.extend_from_slice(&(edits.len() as u32).to_be_bytes());
+    for edit in edits {
+        let (kind, level, id) = match *edit {
+            VersionEdit::Add { level, id } => (1, level, id),
+            VersionEdit::Remove { level, id } => (2, level, id),
+            VersionEdit::Truncate { sequence } => (3, 0, sequence),
+        };
+        payload.push(kind);
+        payload.extend_from_slice(&(level as u32).to_be_bytes());
+        payload.extend_from_slice(&id.to_be_bytes());
+    }
+
+    let mut record = Vec::with_capacity(HEADER_SIZE + payload.len());
+    record.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    record.extend_from_slice(&crc32fast::hash(&payload).to_be_bytes());
+    record.extend_from_slice(&payload);
+    record
+}
+
+/// Decodes the payload of a MANIFEST record into its edits.
+fn decode_payload(payload: &[u8]) -> Option<Vec<VersionEdit>> {
+    let count = u32::from_be_bytes(payload.get(0..4)?.try_into().ok()?) as usize;
+    let mut edits = Vec::new();
+    for i in 0..count {
+        let edit = payload.get(4 + i * 13..4 + (i + 1) * 13)?;
+        let level = u32::from_be_bytes(edit[1..5].try_into().ok()?) as usize;
+        let id = u64::from_be_bytes(edit[5..13].try_into().ok()?);
+        edits.push(match edit[0] {
+            1 => VersionEdit::Add { level, id },
+            2 => VersionEdit::Remove { level, id },
+            3 => VersionEdit::Truncate { sequence: id },
+            _ => return None,
+        });
+    }
+
+    Some(edits)
+}
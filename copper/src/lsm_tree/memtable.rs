@@ -1,14 +1,29 @@
-use super::entry::Entry;
-use bloomfilter::Bloom;
-use std::{collections::BTreeMap, fmt::Debug};
+use super::entry::{self, Entry, Lookup};
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    ops::Bound,
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
 
 /// `Memtable` struct represents an in-memory table in a database.
-/// It has a `BTreeMap` of entries, a size, a maximum size, and a bloom filter.
+/// It has a `BTreeMap` of the versions of each key, the range tombstones written to it, a size and a maximum size.
+/// Lookups in the `BTreeMap` are exact, so unlike an `SSTable` it needs no bloom filter.
+///
+/// A `Memtable` is shared with the snapshots of the table, which read it at their own sequence number while it takes new writes.
+/// A write keeps the older versions of its key some open snapshot still sees, and drops the others.
+/// Merge operands are kept apart from the versions below them, so the versions below the operands are kept too.
 pub struct Memtable {
-    entries: BTreeMap<Vec<u8>, Entry>,
-    size: usize,
+    state: RwLock<State>,
     max_size: usize,
-    bloom_filter: Bloom<Vec<u8>>,
+}
+
+/// Content of a `Memtable`, the versions of each key are sorted from the newest to the oldest.
+#[derive(Default)]
+struct State {
+    entries: BTreeMap<Vec<u8>, Vec<Entry>>,
+    range_tombstones: Vec<Entry>,
+    size: usize,
 }
 
 impl Default for Memtable {
@@ -19,14 +34,47 @@ impl Default for Memtable {
 }
 
 impl Memtable {
-    /// Creates a new `Memtable` with an empty `BTreeMap` of entries, a size of 0, a maximum size of 32, and a new bloom filter.
+    /// Creates a new `Memtable` with an empty `BTreeMap` of entries, a size of 0 and a maximum size of 32.
     pub fn new() -> Self {
-        Self { entries: BTreeMap::new(), size: 0, max_size: 32, bloom_filter: Bloom::new_for_fp_rate(1000, 0.01) }
+        Self { state: RwLock::default(), max_size: 32 }
+    }
+
+    /// Sets the maximum size of the `Memtable`, once its size reaches it the `Memtable` is full.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_size` - The maximum size in bytes.
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
     }
 
-    /// Returns all entries in the `Memtable`.
+    /// Locks the content for reading, recovering it if a thread panicked while holding it.
+    fn read(&self) -> RwLockReadGuard<'_, State> {
+        self.state.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Locks the content for writing, recovering it if a thread panicked while holding it.
+    fn write(&self) -> RwLockWriteGuard<'_, State> {
+        self.state.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Returns all entries in the `Memtable` in key order, the versions of a key the newest first.
     pub fn get_all_entries(&self) -> Vec<Entry> {
-        self.entries.iter().map(|(key, entry)| Entry::new(key, entry.get_value(), entry.is_deleted())).collect()
+        self.read().entries.values().flatten().cloned().collect()
+    }
+
+    /// Returns an iterator over the entries in the `Memtable` between two bounds, tombstones included, see `MemtableIterator`.
+    /// In key order, the versions of a key come the newest first, in reverse key order, the oldest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The lower bound of the keys.
+    /// * `end` - The upper bound of the keys.
+    /// * `reverse` - Whether to go through the entries in reverse key order.
+    /// * `sequence` - The sequence number of the last write to see, newer versions are skipped.
+    pub fn range(self: &Arc<Self>, start: Bound<&[u8]>, end: Bound<&[u8]>, reverse: bool, sequence: u64) -> MemtableIterator {
+        MemtableIterator { memtable: Arc::clone(self), start: start.map(<[u8]>::to_vec), end: end.map(<[u8]>::to_vec), reverse, sequence, last: None }
     }
 
     /// Inserts a new entry into the `Memtable`.
@@ -36,80 +84,255 @@ impl Memtable {
     /// * `key` - A byte slice that holds the key of the entry.
     /// * `value` - A byte slice that holds the value of the entry.
     /// * `deleted` - A boolean indicating whether the entry is deleted.
+    /// * `sequence` - The sequence number of the write.
+    /// * `snapshots` - The sequence numbers of the open snapshots, whose versions of the key are kept.
     ///
-    /// Returns `true` if an entry with the same key already exists and is replaced, `false` otherwise.
-    pub fn insert(&mut self, key: &[u8], value: &[u8], deleted: bool) -> bool {
-        self.bloom_filter.set(&key.to_vec());
+    /// Returns `true` if the key already holds an entry, which the new one replaces, `false` otherwise.
+    pub fn insert(&self, key: &[u8], value: &[u8], deleted: bool, sequence: u64, snapshots: &[u64]) -> bool {
+        self.insert_entry(Entry::new(key, value, deleted, sequence), snapshots)
+    }
 
-        let entry = Entry::new(key, value, deleted);
-        let previous_entry = self.entries.insert(key.to_vec(), Entry::new(entry.get_key(), entry.get_value(), entry.is_deleted()));
-        match previous_entry {
-            Some(previous_entry) => {
-                self.size = self.size + entry.get_size() - previous_entry.get_size();
-                true
-            }
-            None => {
-                self.size += entry.get_size();
-                false
-            }
+    /// Inserts an entry of any kind into the `Memtable`, see `insert`.
+    /// A range tombstone is added with `delete_range`.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The entry, stamped with the sequence number of the write.
+    /// * `snapshots` - The sequence numbers of the open snapshots, whose versions of the key are kept.
+    pub fn insert_entry(&self, entry: Entry, snapshots: &[u64]) -> bool {
+        if entry.is_range_tombstone() {
+            return self.delete_range(entry, snapshots);
         }
+
+        let mut state = self.write();
+        let state = &mut *state;
+        let covering = entry::newest_covering(&state.range_tombstones, entry.get_key(), u64::MAX).unwrap_or_default();
+        let versions = state.entries.entry(entry.get_key().to_vec()).or_default();
+        let replaced = versions.first().is_some_and(|newest| newest.get_sequence() > covering);
+
+        let sequence = entry.get_sequence();
+        state.size += entry.get_size();
+        versions.insert(0, entry);
+        state.size -= drop_hidden(versions, 1, sequence, snapshots);
+        replaced
     }
 
-    /// Returns the entry with the given key if it exists and the bloom filter indicates that it might be in the `Memtable`.
+    /// Adds a range tombstone to the `Memtable`, it hides the entries it covers, and drops the ones no open snapshot sees.
     ///
     /// # Arguments
     ///
-    /// * `key` - A byte slice that holds the key of the entry.
-    pub fn get(&self, key: &[u8]) -> Option<Entry> {
-        if !self.bloom_filter.check(&key.to_vec()) {
-            return None;
+    /// * `tombstone` - The range tombstone, stamped with the sequence number of the write.
+    /// * `snapshots` - The sequence numbers of the open snapshots, whose versions of the keys are kept.
+    ///
+    /// Returns `true` if it covers any entry, `false` otherwise.
+    pub fn delete_range(&self, tombstone: Entry, snapshots: &[u64]) -> bool {
+        let mut state = self.write();
+        let state = &mut *state;
+        let mut covered = false;
+        if tombstone.get_key() < tombstone.get_value() {
+            let mut freed = 0;
+            for versions in state.entries.range_mut::<[u8], _>((Bound::Included(tombstone.get_key()), Bound::Excluded(tombstone.get_value()))).map(|(_, versions)| versions) {
+                covered = true;
+                freed += drop_hidden(versions, 0, tombstone.get_sequence(), snapshots);
+            }
+            state.entries.retain(|_, versions| !versions.is_empty());
+            state.size -= freed;
         }
 
-        let entry = self.entries.get(key);
-        entry.map(|entry| Entry::new(entry.get_key(), entry.get_value(), entry.is_deleted()))
+        state.size += tombstone.get_size();
+        state.range_tombstones.push(tombstone);
+        covered
     }
 
-    /// Returns a range of entries in the `Memtable` from the start key to the end key, inclusive.
+    /// Looks up the newest version of the given key in the `Memtable`.
     ///
     /// # Arguments
     ///
-    /// * `start` - A byte slice that holds the start key of the range.
-    /// * `end` - A byte slice that holds the end key of the range.
+    /// * `key` - A byte slice that holds the key of the entry.
     ///
-    /// The function does not return deleted entries.
-    pub fn get_range(&self, start: &[u8], end: &[u8]) -> Vec<Entry> {
-        let start_key = start.to_vec();
-        let end_key = end.to_vec();
+    /// Returns `Lookup::Deleted` if the key holds a tombstone or if a range tombstone covers it.
+    pub fn get(&self, key: &[u8]) -> Lookup {
+        self.get_at(key, u64::MAX)
+    }
 
-        self.entries.range(start_key..=end_key).filter_map(|(key, entry)| if entry.is_deleted() { None } else { Some(Entry::new(key, entry.get_value(), entry.is_deleted())) }).collect()
+    /// Looks up the given key in the `Memtable` as it was at a sequence number, see `get`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A byte slice that holds the key of the entry.
+    /// * `sequence` - The sequence number of the last write to see.
+    pub fn get_at(&self, key: &[u8], sequence: u64) -> Lookup {
+        let state = self.read();
+        let newest = state.entries.get(key).and_then(|versions| versions.iter().find(|entry| entry.get_sequence() <= sequence));
+        Lookup::from(newest).covered_by(entry::newest_covering(&state.range_tombstones, key, sequence))
+    }
+
+    /// Returns the range tombstones of the `Memtable`, the oldest first.
+    pub fn get_range_tombstones(&self) -> Vec<Entry> {
+        self.read().range_tombstones.clone()
     }
 
     /// Returns whether the `Memtable` is full, i.e., its size is greater than or equal to its maximum size.
     pub fn is_full(&self) -> bool {
-        self.size >= self.max_size
+        self.get_size() >= self.max_size
     }
 
     /// Returns the size of the `Memtable`.
     pub fn get_size(&self) -> usize {
-        self.size
+        self.read().size
     }
 
-    /// Returns a reference to the `BTreeMap` of entries in the `Memtable`.
-    pub fn get_entries(&self) -> &BTreeMap<Vec<u8>, Entry> {
-        &self.entries
+    /// Returns whether the `Memtable` holds no entry and no range tombstone.
+    pub fn is_empty(&self) -> bool {
+        let state = self.read();
+        state.entries.is_empty() && state.range_tombstones.is_empty()
     }
+}
+
+/// `MemtableIterator` struct goes through the entries of a `Memtable` between two bounds, see `Memtable::range`.
+///
+/// It holds the `Memtable` through its `Arc` and copies nothing up front: each step looks up the entry following the last one returned,
+/// so the `Memtable` can take writes meanwhile, and the versions newer than its sequence number are skipped.
+pub struct MemtableIterator {
+    memtable: Arc<Memtable>,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    reverse: bool,
+    sequence: u64,
+    /// The key and the sequence number of the last entry returned.
+    last: Option<(Vec<u8>, u64)>,
+}
+
+impl Iterator for MemtableIterator {
+    type Item = Entry;
+
+    /// Returns the next entry, the next version of the last key returned or the first one of the following key.
+    fn next(&mut self) -> Option<Entry> {
+        let state = self.memtable.read();
+        let last = self.last.take();
+        let (mut start, mut end) = (self.start.as_ref().map(Vec::as_slice), self.end.as_ref().map(Vec::as_slice));
+        match (&last, self.reverse) {
+            (Some((key, _)), false) => start = Bound::Included(key.as_slice()),
+            (Some((key, _)), true) => end = Bound::Included(key.as_slice()),
+            (None, _) => {}
+        }
 
-    /// Clears the `Memtable`, removing all entries, setting the size to 0, and clearing the bloom filter.
-    pub fn clear(&mut self) {
-        self.entries.clear();
-        self.size = 0;
-        self.bloom_filter.clear();
+        let mut keys = state.entries.range::<[u8], _>((start, end));
+        loop {
+            let (key, versions) = if self.reverse { keys.next_back()? } else { keys.next()? };
+            let after = last.as_ref().filter(|(last, _)| last == key).map(|&(_, sequence)| sequence);
+            let visible = |entry: &&Entry| entry.get_sequence() <= self.sequence;
+            let entry = if self.reverse {
+                versions.iter().rev().filter(visible).find(|entry| after.is_none_or(|after| entry.get_sequence() > after))
+            } else {
+                versions.iter().filter(visible).find(|entry| after.is_none_or(|after| entry.get_sequence() < after))
+            };
+
+            if let Some(entry) = entry {
+                self.last = Some((key.clone(), entry.get_sequence()));
+                return Some(entry.clone());
+            }
+        }
+    }
+}
+
+/// Drops the versions of a key no open snapshot sees, from a position in its versions down to the oldest one.
+/// Returns the size of the dropped versions.
+///
+/// A version is seen by the snapshots taken from its write until the write hiding it: the next newer version,
+/// or for the first one, the write at the given sequence number. A version below merge operands that are kept is kept too.
+fn drop_hidden(versions: &mut Vec<Entry>, from: usize, hidden_at: u64, snapshots: &[u64]) -> usize {
+    let mut freed = 0;
+    let mut hidden_at = hidden_at;
+    let mut needed = from > 0 && versions[from - 1].is_merge();
+    let mut position = from;
+    while let Some(version) = versions.get(position) {
+        let sequence = version.get_sequence();
+        if needed || snapshots.iter().any(|&snapshot| sequence <= snapshot && snapshot < hidden_at) {
+            needed = version.is_merge();
+            position += 1;
+        } else {
+            freed += versions.remove(position).get_size();
+        }
+        hidden_at = sequence;
     }
+
+    freed
 }
 
 impl Debug for Memtable {
     /// Formats the `Memtable` for printing.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Memtable").field("size", &self.size).field("max_size", &self.max_size).field("entries", &self.entries).finish()
+        let state = self.read();
+        f.debug_struct("Memtable").field("size", &state.size).field("max_size", &self.max_size).field("entries", &state.entries).field("range_tombstones", &state.range_tombstones).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsm_tree::entry::EntryKind;
+
+    fn found(value: &[u8], sequence: u64) -> Lookup {
+        Lookup::Found { value: value.to_vec(), sequence }
+    }
+
+    #[test]
+    fn keeps_the_versions_open_snapshots_see() {
+        let memtable = Memtable::new();
+        memtable.insert(b"a", b"1", false, 1, &[]);
+        memtable.insert(b"a", b"2", false, 2, &[]);
+        assert_eq!(memtable.get_all_entries().len(), 1);
+
+        // The snapshot at 3 sees the version written at 2, not the one written at 4
+        memtable.insert(b"a", b"4", false, 4, &[3]);
+        memtable.insert(b"a", b"5", false, 5, &[3]);
+        assert_eq!(memtable.get_at(b"a", 3), found(b"2", 2));
+        assert_eq!(memtable.get(b"a"), found(b"5", 5));
+        assert_eq!(memtable.get_all_entries().len(), 2);
+
+        // A range tombstone hides the versions from the newer readers only
+        memtable.delete_range(Entry::with_kind(b"a", b"b", EntryKind::RangeTombstone, 6), &[3]);
+        assert_eq!(memtable.get_at(b"a", 3), found(b"2", 2));
+        assert_eq!(memtable.get(b"a"), Lookup::Deleted { sequence: 6 });
+
+        // Once the snapshot is gone, the next write drops them
+        memtable.delete_range(Entry::with_kind(b"a", b"b", EntryKind::RangeTombstone, 7), &[]);
+        assert!(memtable.get_all_entries().is_empty());
+    }
+
+    #[test]
+    fn keeps_the_versions_below_merge_operands() {
+        let memtable = Memtable::new();
+        memtable.insert(b"a", b"1", false, 1, &[]);
+        memtable.insert_entry(Entry::with_kind(b"a", b"x", EntryKind::Merge, 2), &[]);
+        memtable.insert_entry(Entry::with_kind(b"a", b"y", EntryKind::Merge, 3), &[]);
+        let sequences: Vec<u64> = memtable.get_all_entries().iter().map(Entry::get_sequence).collect();
+        assert_eq!(sequences, vec![3, 2, 1]);
+
+        // A value hides them all
+        memtable.insert(b"a", b"4", false, 4, &[]);
+        assert_eq!(memtable.get_all_entries().len(), 1);
+        assert_eq!(memtable.get_size(), memtable.get_all_entries()[0].get_size());
+    }
+
+    #[test]
+    fn goes_through_the_versions_while_taking_writes() {
+        let memtable = Arc::new(Memtable::new());
+        memtable.insert(b"a", b"1", false, 1, &[]);
+        memtable.insert(b"b", b"2", false, 2, &[]);
+        memtable.insert(b"b", b"3", false, 3, &[2]);
+        memtable.insert(b"c", b"4", false, 4, &[2]);
+
+        let entries = |iterator: MemtableIterator| -> Vec<(Vec<u8>, u64)> { iterator.map(|entry| (entry.get_key().to_vec(), entry.get_sequence())).collect() };
+        assert_eq!(entries(memtable.range(Bound::Unbounded, Bound::Unbounded, false, 3)), vec![(b"a".to_vec(), 1), (b"b".to_vec(), 3), (b"b".to_vec(), 2)]);
+        assert_eq!(entries(memtable.range(Bound::Excluded(b"a"), Bound::Unbounded, true, u64::MAX)), vec![(b"c".to_vec(), 4), (b"b".to_vec(), 2), (b"b".to_vec(), 3)]);
+
+        // The writes made meanwhile are skipped by sequence number, the next step continues after the last entry returned
+        let mut iterator = memtable.range(Bound::Unbounded, Bound::Unbounded, false, 4);
+        assert_eq!(iterator.next().map(|entry| entry.get_sequence()), Some(1));
+        memtable.insert(b"a0", b"5", false, 5, &[2, 4]);
+        memtable.insert(b"b", b"6", false, 6, &[2, 4]);
+        assert_eq!(entries(iterator), vec![(b"b".to_vec(), 3), (b"b".to_vec(), 2), (b"c".to_vec(), 4)]);
     }
 }
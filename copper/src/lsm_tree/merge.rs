@@ -0,0 +1,291 @@
+use std::{fmt::Debug, sync::Arc};
+
+use super::{
+    column::{Column, DataType},
+    entry::{Entry, EntryKind},
+    error::{CopperError, Result},
+    row::{decode_row, encode_row},
+};
+
+/// `MergeOperator` trait folds the operands written with `LsmTree::merge` into the row of a key.
+///
+/// A merge is written without reading the key: the operands are stored as they come
+/// and folded into the row only when the key is read, or when a compaction meets the older versions of the key.
+/// The operator of a table is given in its `Options`, it must fold the operands the same way every time the table is opened.
+pub trait MergeOperator: Debug + Send + Sync {
+    /// Checks an operand before it is written.
+    /// Returns an `InvalidValue` error if the operator cannot fold it, every operand is accepted by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `columns` - The `Column`s of the table.
+    /// * `operand` - A byte slice that holds the operand.
+    fn check(&self, _columns: &[Column], _operand: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Folds operands into the row of a key.
+    ///
+    /// # Arguments
+    ///
+    /// * `columns` - The `Column`s of the table.
+    /// * `existing` - The encoded row of the key before the operands, `None` if the key was absent or deleted.
+    /// * `operands` - The operands, the oldest first.
+    ///
+    /// Returns the new encoded row of the key, or `None` to leave it absent.
+    fn merge(&self, columns: &[Column], existing: Option<&[u8]>, operands: &[&[u8]]) -> Result<Option<Vec<u8>>>;
+}
+
+/// `AddInt` struct is a `MergeOperator` adding its operands to an `Int` column.
+/// Each operand is a 4 bytes integer, like the values of `Int` columns, and the sum saturates instead of overflowing.
+/// Merging into an absent or deleted key leaves it absent, as its other columns have no value.
+#[derive(Debug, Clone)]
+pub struct AddInt {
+    column: String,
+}
+
+/// `Merger` struct applies the `MergeOperator` of a table, if it has one, with the `Column`s of the table.
+#[derive(Debug, Clone, Default)]
+pub struct Merger {
+    operator: Option<Arc<dyn MergeOperator>>,
+    columns: Vec<Column>,
+}
+
+impl AddInt {
+    /// Creates a new `AddInt` adding to the column with the given name.
+    ///
+    /// # Arguments
+    ///
+    /// * `column` - The name of an `Int` column of the table.
+    pub fn new(column: &str) -> Self {
+        Self { column: column.to_string() }
+    }
+
+    /// Returns the position of the column among the columns of the table.
+    /// Returns an `InvalidValue` error if the table has no such `Int` column.
+    fn position(&self, columns: &[Column]) -> Result<usize> {
+        columns
+            .iter()
+            .position(|column| column.get_name() == self.column && *column.get_data_type() == DataType::Int)
+            .ok_or_else(|| CopperError::InvalidValue(format!("No Int column {} to add to", self.column)))
+    }
+}
+
+impl MergeOperator for AddInt {
+    /// Checks that the column is an `Int` column of the table and that the operand is a 4 bytes integer.
+    fn check(&self, columns: &[Column], operand: &[u8]) -> Result<()> {
+        self.position(columns)?;
+        if operand.len() != 4 {
+            return Err(CopperError::InvalidValue(format!("Adding to column {} expects a 4 bytes integer", self.column)));
+        }
+
+        Ok(())
+    }
+
+    /// Adds the operands to the column of the existing row.
+    fn merge(&self, columns: &[Column], existing: Option<&[u8]>, operands: &[&[u8]]) -> Result<Option<Vec<u8>>> {
+        let Some(existing) = existing else {
+            return Ok(None);
+        };
+
+        let position = self.position(columns)?;
+        let mut values = decode_row(columns, existing)?;
+        let mut total = i32::from_ne_bytes(values[position].as_slice().try_into().unwrap_or_default());
+        for operand in operands {
+            let operand: [u8; 4] = (*operand).try_into().map_err(|_| CopperError::InvalidValue(format!("Adding to column {} expects a 4 bytes integer", self.column)))?;
+            total = total.saturating_add(i32::from_ne_bytes(operand));
+        }
+        values[position] = total.to_ne_bytes().to_vec();
+
+        encode_row(columns, &values).map(Some)
+    }
+}
+
+impl Merger {
+    /// Creates a new `Merger`.
+    ///
+    /// # Arguments
+    ///
+    /// * `operator` - The `MergeOperator` of the table, if it has one.
+    /// * `columns` - The `Column`s of the table.
+    pub fn new(operator: Option<Arc<dyn MergeOperator>>, columns: Vec<Column>) -> Self {
+        Self { operator, columns }
+    }
+
+    /// Returns true if the table has a `MergeOperator`.
+    pub fn has_operator(&self) -> bool {
+        self.operator.is_some()
+    }
+
+    /// Returns the `MergeOperator` of the table, or a `NoMergeOperator` error if it has none.
+    fn operator(&self) -> Result<&dyn MergeOperator> {
+        self.operator.as_deref().ok_or(CopperError::NoMergeOperator)
+    }
+
+    /// Checks an operand before it is written, see `MergeOperator::check`.
+    ///
+    /// # Arguments
+    ///
+    /// * `operand` - A byte slice that holds the operand.
+    pub fn check(&self, operand: &[u8]) -> Result<()> {
+        self.operator()?.check(&self.columns, operand)
+    }
+
+    /// Folds operands into the row of a key and returns the result as a value, or as a tombstone if the key is left absent.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A byte slice that holds the key.
+    /// * `existing` - The encoded row of the key before the operands, `None` if the key was absent or deleted.
+    /// * `operands` - The operands, the oldest first.
+    /// * `sequence` - The sequence number of the resulting entry.
+    pub fn apply(&self, key: &[u8], existing: Option<&[u8]>, operands: &[&[u8]], sequence: u64) -> Result<Entry> {
+        match self.operator()?.merge(&self.columns, existing, operands)? {
+            Some(row) => Ok(Entry::new(key, &row, false, sequence)),
+            None => Ok(Entry::new(key, &[], true, sequence)),
+        }
+    }
+
+    /// Folds the newest version of a key, if it holds operands, with its older versions, into a value or a tombstone.
+    ///
+    /// # Arguments
+    ///
+    /// * `versions` - Versions of the key, the newest first.
+    /// * `complete` - Whether the key has no version older than the ones given.
+    ///
+    /// The operands of the newest versions are folded into the first value or tombstone below them,
+    /// or into an absent key if there is none and the versions are complete.
+    /// Returns `None` if the versions hold only operands and are not complete, there is nothing to fold them into yet.
+    pub fn fold(&self, versions: &[Entry], complete: bool) -> Result<Option<Entry>> {
+        let Some(newest) = versions.first() else {
+            return Ok(None);
+        };
+        if !newest.is_merge() {
+            return Ok(Some(newest.clone()));
+        }
+
+        // Gather the operands down to the first value or tombstone
+        let mut lists = Vec::new();
+        let mut existing = None;
+        let mut found = complete;
+        for version in versions {
+            if version.is_merge() {
+                lists.push(version.get_value());
+                continue;
+            }
+
+            if version.get_kind() == EntryKind::Value {
+                existing = Some(version.get_value());
+            }
+            found = true;
+            break;
+        }
+        if !found {
+            return Ok(None);
+        }
+
+        let mut operands = Vec::new();
+        for list in lists.iter().rev() {
+            operands.extend(decode_operands(list)?);
+        }
+
+        self.apply(newest.get_key(), existing, &operands, newest.get_sequence()).map(Some)
+    }
+}
+
+/// Appends an operand to the value of an `Entry` holding operands, where each operand is prefixed with its length as a big endian `u32`.
+///
+/// # Arguments
+///
+/// * `operands` - The encoded operands, the oldest first.
+/// * `operand` - A byte slice that holds the operand to append.
+pub fn append_operand(operands: &mut Vec<u8>, operand: &[u8]) {
+    operands.extend_from_slice(&(operand.len() as u32).to_be_bytes());
+    operands.extend_from_slice(operand);
+}
+
+/// Decodes the operands appended with `append_operand`, the oldest first.
+/// Returns an `InvalidValue` error if they are truncated.
+///
+/// # Arguments
+///
+/// * `data` - A byte slice that holds the encoded operands.
+pub fn decode_operands(mut data: &[u8]) -> Result<Vec<&[u8]>> {
+    let truncated = || CopperError::InvalidValue("Truncated merge operands".to_string());
+
+    let mut operands = Vec::new();
+    while !data.is_empty() {
+        let (len, rest) = data.split_first_chunk::<4>().ok_or_else(truncated)?;
+        let len = u32::from_be_bytes(*len) as usize;
+        let operand = rest.get(..len).ok_or_else(truncated)?;
+        operands.push(operand);
+        data = &rest[len..];
+    }
+
+    Ok(operands)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::AddInt;
+    use crate::lsm_tree::{
+        column::{Column, DataType},
+        compaction::LeveledCompaction,
+        error::CopperError,
+        options::Options,
+        tree::LsmTree,
+    };
+
+    fn count(table: &LsmTree, key: &[u8]) -> Option<i32> {
+        table.get(key).unwrap().map(|row| table.decode(&row).unwrap()["Count"].get_int())
+    }
+
+    #[test]
+    fn folds_operands_across_flushes_compactions_and_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("table").to_string_lossy().into_owned();
+        let columns = vec![Column::new("Name", DataType::Text), Column::new("Count", DataType::Int)];
+        let options = |operator: bool| Options {
+            merge_operator: operator.then(|| Arc::new(AddInt::new("Count")) as _),
+            compaction: Arc::new(LeveledCompaction { level0_file_limit: 2, ..Default::default() }),
+            memtable_size: 4096,
+            ..Default::default()
+        };
+        let mut table = LsmTree::create(path.clone(), columns.clone(), options(true)).unwrap();
+        table.insert(b"k", &[b"k".to_vec(), 10_i32.to_ne_bytes().to_vec()]).unwrap();
+        table.insert(b"gone", &[b"gone".to_vec(), 10_i32.to_ne_bytes().to_vec()]).unwrap();
+        table.flush().unwrap();
+
+        // The operands are spread over an SSTable of the first level, the SSTables compacted with the value and the memtable
+        table.merge(b"k", &1_i32.to_ne_bytes()).unwrap();
+        table.delete(b"gone").unwrap();
+        table.merge(b"gone", &1_i32.to_ne_bytes()).unwrap();
+        table.merge(b"absent", &1_i32.to_ne_bytes()).unwrap();
+        table.flush().unwrap();
+        assert_eq!(count(&table, b"k"), Some(11));
+        table.merge(b"k", &2_i32.to_ne_bytes()).unwrap();
+        table.flush().unwrap();
+        table.wait_for_compaction().unwrap();
+        table.merge(b"k", &4_i32.to_ne_bytes()).unwrap();
+        assert!(table.get_compaction_stats().get_compactions() > 0);
+
+        assert_eq!(count(&table, b"k"), Some(17));
+        assert_eq!(count(&table, b"gone"), None);
+        assert_eq!(count(&table, b"absent"), None);
+        assert_eq!(table.iter().unwrap().count(), 1);
+
+        // The operand still in the WAL is folded again once the table is opened, but only with a merge operator
+        drop(table);
+        let table = LsmTree::open(path.clone(), columns.clone(), options(false)).unwrap();
+        assert!(matches!(table.get(b"k"), Err(CopperError::NoMergeOperator)));
+        drop(table);
+        let mut table = LsmTree::open(path, columns, options(true)).unwrap();
+        assert_eq!(count(&table, b"k"), Some(17));
+        table.merge(b"k", &8_i32.to_ne_bytes()).unwrap();
+        table.flush().unwrap();
+        table.wait_for_compaction().unwrap();
+        assert_eq!(count(&table, b"k"), Some(25));
+    }
+}
@@ -0,0 +1,263 @@
+use std::{
+    fs,
+    io::{BufRead, BufReader, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use super::{
+    column::Column,
+    config::{self, FORMAT_VERSION},
+    entry::Entry,
+    error::{CopperError, Result},
+    manifest::{self, Manifest},
+    options::Options,
+    row::{decode_legacy_row, encode_row},
+    sstable::SSTable,
+    wal::{SyncPolicy, Wal},
+};
+
+/// Migrates a table written before the format was versioned to the current format.
+///
+/// # Arguments
+///
+/// * `table_path` - A reference to the path of the table.
+/// * `columns` - A slice of the `Column`s of the table.
+/// * `format` - The format version currently recorded for the table, 0 for the tables this migrates.
+/// * `options` - The `Options` of the table, used to write the converted SSTables.
+///
+/// The converted files are staged in a `migration` folder inside the table.
+/// Once everything is staged, a `DONE` marker is written and the staged files are moved over the old ones,
+/// `config.txt` last, so a crash at any point either restarts or resumes the migration on the next load.
+///
+/// Legacy rows that cannot be split back into their columns (because a value contained a `|`,
+/// or the WAL mangled it) are dropped. Returns the number of dropped rows.
+pub fn migrate(table_path: &Path, columns: &[Column], format: u32, options: &Options) -> Result<usize> {
+    if format >= FORMAT_VERSION {
+        return Err(CopperError::UnsupportedFormat(format));
+    }
+
+    let staging = table_path.join("migration");
+
+    // A previous migration was fully staged, finish installing it
+    if staging.join("DONE").exists() {
+        install(table_path, &staging)?;
+        return Ok(0);
+    }
+
+    // Otherwise start over from the original files
+    let _ = fs::remove_dir_all(&staging);
+    fs::create_dir_all(staging.join("ssts"))?;
+
+    // Convert every SSTable to the block format, in the order of the levels, and list them in a new MANIFEST
+    let mut dropped = 0;
+    let mut levels: Vec<Vec<u64>> = Vec::new();
+    for (id, (level, file_path)) in legacy_sstables(table_path)?.into_iter().enumerate() {
+        let mut entries = Vec::new();
+        for entry in read_legacy_sstable(&file_path)? {
+            match migrate_entry(columns, entry) {
+                Some(entry) => entries.push(entry),
+                None => dropped += 1,
+            }
+        }
+
+        SSTable::create(&staging.join("ssts").join(format!("sst_{}_{}.sst", level, id)), entries, Vec::new(), options)?;
+        while levels.len() <= level {
+            levels.push(Vec::new());
+        }
+        levels[level].insert(0, id as u64);
+    }
+    Manifest::create(&staging, &levels, 0)?;
+
+    // Convert the WAL, keeping the order of its entries
+    let wal = Wal::open(&staging, SyncPolicy::Never)?;
+    let mut sequence = 0;
+    for entry in read_legacy_wal(table_path)? {
+        let Some(entry) = migrate_entry(columns, entry) else {
+            dropped += 1;
+            continue;
+        };
+        sequence += 1;
+        wal.append(&Entry::new(entry.get_key(), entry.get_value(), entry.is_deleted(), sequence))?;
+    }
+    wal.sync()?;
+
+    config::write_config(&staging, columns)?;
+    fs::write(staging.join("DONE"), [])?;
+
+    install(table_path, &staging)?;
+    Ok(dropped)
+}
+
+/// Moves the staged files of a migration over the files of the table, then removes the staging folder.
+/// The legacy SSTables are deleted once the converted ones are in place, and the directories are synced after the renames.
+fn install(table_path: &Path, staging: &Path) -> Result<()> {
+    fs::create_dir_all(table_path.join("ssts"))?;
+    for file in fs::read_dir(staging.join("ssts"))? {
+        let file = file?;
+        fs::rename(file.path(), table_path.join("ssts").join(file.file_name()))?;
+    }
+    manifest::sync_dir(&table_path.join("ssts"))?;
+    for (_, file_path) in legacy_sstables(table_path)? {
+        fs::remove_file(file_path)?;
+    }
+
+    // Files already moved by an interrupted install are simply missing
+    if staging.join("MANIFEST").exists() {
+        fs::rename(staging.join("MANIFEST"), table_path.join("MANIFEST"))?;
+    }
+    if staging.join("wal.txt").exists() {
+        fs::rename(staging.join("wal.txt"), table_path.join("wal.txt"))?;
+    }
+    if staging.join("config.txt").exists() {
+        fs::rename(staging.join("config.txt"), table_path.join("config.txt"))?;
+    }
+    manifest::sync_dir(table_path)?;
+
+    fs::remove_dir_all(staging)?;
+    Ok(())
+}
+
+/// Re-encodes the value of a legacy entry with the current row format.
+/// Tombstones carry no value.
+/// Returns `None` if the legacy value cannot be converted.
+fn migrate_entry(columns: &[Column], entry: Entry) -> Option<Entry> {
+    if entry.is_deleted() {
+        return Some(Entry::new(entry.get_key(), &[], true, entry.get_sequence()));
+    }
+
+    let values = decode_legacy_row(columns, entry.get_value()).ok()?;
+    Some(Entry::new(entry.get_key(), &encode_row(columns, &values).ok()?, false, entry.get_sequence()))
+}
+
+/// Lists the legacy SSTable files of a table, named `sst_<level>_<position>.txt`, with their level.
+/// Levels come in order and, inside a level, the oldest SSTable comes first,
+/// which is the order in which the converted SSTables must be numbered.
+fn legacy_sstables(table_path: &Path) -> Result<Vec<(usize, PathBuf)>> {
+    let mut files = Vec::new();
+    let dir = match fs::read_dir(table_path.join("ssts")) {
+        Ok(dir) => dir,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    for file in dir {
+        let file_path = file?.path();
+        let name = file_path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let Some((level, position)) = name.strip_prefix("sst_").and_then(|name| name.strip_suffix(".txt")).and_then(|name| name.split_once('_')) else {
+            continue;
+        };
+        if let (Ok(level), Ok(position)) = (level.parse::<usize>(), position.parse::<usize>()) {
+            files.push((level, position, file_path));
+        }
+    }
+
+    // The newest SSTable of a level was written at position 0
+    files.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+    Ok(files.into_iter().map(|(level, _, file_path)| (level, file_path)).collect())
+}
+
+/// Reads the entries of a legacy SSTable file: the number of entries as a big endian `u64`,
+/// then for each entry the key and the value prefixed by their `u64` length, and the tombstone flag.
+/// Returns a `Corruption` error at the offset where the file ends too early.
+fn read_legacy_sstable(path: &Path) -> Result<Vec<Entry>> {
+    let data = fs::read(path)?;
+    let mut offset: usize = 0;
+    let mut read = |len: usize| -> Result<Vec<u8>> {
+        let bytes = offset.checked_add(len).and_then(|end| data.get(offset..end)).ok_or_else(|| CopperError::corruption(path, offset as u64))?;
+        offset += len;
+        Ok(bytes.to_vec())
+    };
+
+    let count = u64::from_be_bytes(read(8)?.try_into().unwrap_or_default());
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let key_len = u64::from_be_bytes(read(8)?.try_into().unwrap_or_default()) as usize;
+        let key = read(key_len)?;
+        let value_len = u64::from_be_bytes(read(8)?.try_into().unwrap_or_default()) as usize;
+        let value = read(value_len)?;
+        let deleted = read(1)?[0] == 1;
+        entries.push(Entry::new(&key, &value, deleted, 0));
+    }
+
+    Ok(entries)
+}
+
+/// Reads the entries of a legacy WAL, where each line was `key|values|deleted flag`.
+fn read_legacy_wal(table_path: &Path) -> Result<Vec<Entry>> {
+    let file = match fs::File::open(table_path.join("wal.txt")) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).split(b'\n') {
+        let line = line?;
+        let parts: Vec<&[u8]> = line.split(|b| *b == b'|').collect();
+
+        // Tombstones were written with a variable number of separators, only the flag matters
+        if parts.len() > 1 && parts[parts.len() - 1] == b"\x01" {
+            entries.push(Entry::new(parts[0], &[], true, 0));
+            continue;
+        }
+
+        // The values are everything between the key and the flag, rows that do not match the columns are dropped later
+        let values = &parts[1..parts.len().saturating_sub(1).max(1)];
+        entries.push(Entry::new(parts[0], &values.join(&b'|'), false, 0));
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsm_tree::{column::DataType, tree::LsmTree};
+
+    /// Removes the format line of the `config.txt` of a table, like the tables written before the format was versioned.
+    fn remove_format(path: &Path) {
+        let config = fs::read_to_string(path.join("config.txt")).unwrap();
+        fs::write(path.join("config.txt"), config.split_once('\n').unwrap().1).unwrap();
+    }
+
+    /// Encodes a legacy SSTable file holding the given keys, values and tombstone flags.
+    fn legacy_sstable(entries: &[(&[u8], &[u8], bool)]) -> Vec<u8> {
+        let mut data = (entries.len() as u64).to_be_bytes().to_vec();
+        for (key, value, deleted) in entries {
+            data.extend((key.len() as u64).to_be_bytes());
+            data.extend(*key);
+            data.extend((value.len() as u64).to_be_bytes());
+            data.extend(*value);
+            data.push(*deleted as u8);
+        }
+        data
+    }
+
+    #[test]
+    fn migrates_version_0_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("table");
+        let columns = vec![Column::new("Name", DataType::Text), Column::new("City", DataType::Text)];
+        fs::create_dir_all(&path).unwrap();
+        config::write_config(&path, &columns).unwrap();
+        remove_format(&path);
+
+        // The rows were joined with `|`, a value holding one cannot be split back and is dropped
+        fs::create_dir_all(path.join("ssts")).unwrap();
+        fs::write(path.join("ssts").join("sst_0_1.txt"), legacy_sstable(&[(b"a", b"ann|paris", false), (b"b", b"bob|rome", false)])).unwrap();
+        fs::write(path.join("ssts").join("sst_0_0.txt"), legacy_sstable(&[(b"b", b"", true), (b"c", b"c|a|rol", false)])).unwrap();
+        fs::write(path.join("wal.txt"), b"d|dan|oslo|\x00\na|\x01\ne|eve|lima|\x00\n").unwrap();
+
+        let table = LsmTree::open(path.to_string_lossy().into_owned(), columns, Options::default()).unwrap();
+        assert_eq!(table.get(b"a").unwrap(), None);
+        assert_eq!(table.get(b"b").unwrap(), None);
+        assert_eq!(table.get(b"c").unwrap(), None);
+        for (key, name, city) in [(b"d", "dan", "oslo"), (b"e", "eve", "lima")] {
+            let row = table.decode(&table.get(key).unwrap().unwrap()).unwrap();
+            assert_eq!((row["Name"].get_text(), row["City"].get_text()), (name.to_string(), city.to_string()));
+        }
+        assert_eq!(config::read_config(&path).unwrap().0, FORMAT_VERSION);
+        assert!(fs::read_dir(path.join("ssts")).unwrap().all(|file| file.unwrap().path().extension().unwrap() == "sst"));
+        assert!(!path.join("migration").exists());
+    }
+}
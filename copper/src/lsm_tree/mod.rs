@@ -1,7 +1,23 @@
+pub mod background;
+pub mod batch;
 pub mod column;
+pub mod compaction;
+pub mod config;
+pub mod cursor;
+pub mod db;
 pub mod entry;
+pub mod error;
+pub mod iterator;
+pub mod lock;
 pub mod log;
+pub mod manifest;
 pub mod memtable;
+pub mod merge;
+pub mod migration;
+pub mod options;
+pub mod row;
+pub mod snapshot;
 pub mod sstable;
+pub mod transaction;
 pub mod tree;
 pub mod wal;
@@ -0,0 +1,37 @@
+use std::sync::Arc;
+
+use super::{
+    compaction::{CompactionStrategy, LeveledCompaction},
+    merge::MergeOperator,
+    wal::SyncPolicy,
+};
+
+/// `Options` struct holds the settings used when a table is opened.
+/// Fields left out can be filled with `..Default::default()`.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// When the Write-Ahead Log (WAL) is synced to disk.
+    pub sync_policy: SyncPolicy,
+    /// The target size in bytes of a data block of an SSTable, 4 KiB by default.
+    pub block_size: usize,
+    /// The number of bits per key of the bloom filter of an SSTable, 10 by default for about 1% of false positives.
+    /// 0 disables the filters.
+    pub bits_per_key: usize,
+    /// The `CompactionStrategy` that picks the SSTables merged in the background, `LeveledCompaction` by default.
+    pub compaction: Arc<dyn CompactionStrategy>,
+    /// Writes stall once this many full memtables wait to be flushed in the background, 4 by default.
+    pub max_immutable_memtables: usize,
+    /// Writes stall once the first level holds this many SSTables while a compaction is pending, 12 by default.
+    pub level0_stop_writes: usize,
+    /// The `MergeOperator` folding the operands written with `LsmTree::merge`, none by default.
+    pub merge_operator: Option<Arc<dyn MergeOperator>>,
+    /// The size in bytes a memtable reaches before it is flushed to an SSTable, 32 by default.
+    pub memtable_size: usize,
+}
+
+impl Default for Options {
+    /// Creates `Options` with the default settings.
+    fn default() -> Self {
+        Self { sync_policy: SyncPolicy::default(), block_size: 4096, bits_per_key: 10, compaction: Arc::new(LeveledCompaction::default()), max_immutable_memtables: 4, level0_stop_writes: 12, merge_operator: None, memtable_size: 32 }
+    }
+}
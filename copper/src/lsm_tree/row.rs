@@ -0,0 +1,110 @@
+use super::{
+    column::{Column, DataType},
+    error::{CopperError, Result},
+};
+
+/// Version of the row encoding, stored as the first byte of every encoded row.
+pub const ROW_FORMAT_VERSION: u8 = 1;
+
+/// Encodes the values of a row into a single byte vector.
+///
+/// # Arguments
+///
+/// * `columns` - A slice of `Column`s that describes the layout of the row.
+/// * `values` - A slice of byte vectors, one per column.
+///
+/// The row starts with the format version byte, followed by each value in column order.
+/// `Int` values are stored on 4 bytes and `Bool` values on 1 byte,
+/// `Text` values are prefixed with their length as a big endian `u32`.
+pub fn encode_row(columns: &[Column], values: &[Vec<u8>]) -> Result<Vec<u8>> {
+    if values.len() != columns.len() {
+        return Err(CopperError::InvalidValue("Invalid number of values".to_string()));
+    }
+
+    let mut row = vec![ROW_FORMAT_VERSION];
+    for (column, value) in columns.iter().zip(values) {
+        match column.get_data_type() {
+            DataType::Int => {
+                if value.len() != 4 {
+                    return Err(CopperError::InvalidValue(format!("Column {} expects a 4 bytes integer", column.get_name())));
+                }
+            }
+            DataType::Bool => {
+                if value.len() != 1 {
+                    return Err(CopperError::InvalidValue(format!("Column {} expects a 1 byte boolean", column.get_name())));
+                }
+            }
+            DataType::Text => {
+                let len = u32::try_from(value.len()).map_err(|_| CopperError::InvalidValue(format!("Column {} value is too long", column.get_name())))?;
+                row.extend_from_slice(&len.to_be_bytes());
+            }
+        }
+        row.extend_from_slice(value);
+    }
+
+    Ok(row)
+}
+
+/// Decodes a row encoded with `encode_row` back into one byte vector per column.
+///
+/// # Arguments
+///
+/// * `columns` - A slice of `Column`s that describes the layout of the row.
+/// * `data` - A byte slice that holds the encoded row.
+///
+/// Returns an `InvalidValue` error if the version byte is unknown or the row is truncated or too long.
+pub fn decode_row(columns: &[Column], data: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let (version, mut rest) = data.split_first().ok_or_else(|| CopperError::InvalidValue("Empty row".to_string()))?;
+    if *version != ROW_FORMAT_VERSION {
+        return Err(CopperError::InvalidValue(format!("Unknown row format version {}", version)));
+    }
+
+    let mut values = Vec::with_capacity(columns.len());
+    for column in columns {
+        let len = match column.get_data_type() {
+            DataType::Int => 4,
+            DataType::Bool => 1,
+            DataType::Text => {
+                let (len, tail) = split(rest, 4)?;
+                rest = tail;
+                u32::from_be_bytes(len.try_into().unwrap_or_default()) as usize
+            }
+        };
+        let (value, tail) = split(rest, len)?;
+        rest = tail;
+        values.push(value.to_vec());
+    }
+
+    if !rest.is_empty() {
+        return Err(CopperError::InvalidValue("Trailing bytes after row".to_string()));
+    }
+
+    Ok(values)
+}
+
+/// Decodes a row written before the row format was versioned, where values were joined with `|`.
+///
+/// # Arguments
+///
+/// * `columns` - A slice of `Column`s that describes the layout of the row.
+/// * `data` - A byte slice that holds the legacy row.
+///
+/// Returns an `InvalidValue` error if the row does not split into exactly one value per column,
+/// which happens when a value itself contained a `|`.
+pub fn decode_legacy_row(columns: &[Column], data: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let values: Vec<Vec<u8>> = data.split(|b| *b == b'|').map(|value| value.to_vec()).collect();
+    if values.len() != columns.len() {
+        return Err(CopperError::InvalidValue("Legacy row does not match the columns".to_string()));
+    }
+
+    Ok(values)
+}
+
+/// Splits `data` after `len` bytes, failing if it is too short.
+fn split(data: &[u8], len: usize) -> Result<(&[u8], &[u8])> {
+    if data.len() < len {
+        return Err(CopperError::InvalidValue("Truncated row".to_string()));
+    }
+
+    Ok(data.split_at(len))
+}
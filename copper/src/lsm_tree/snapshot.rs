@@ -0,0 +1,220 @@
+use std::{ops::RangeBounds, sync::Arc};
+
+use super::{
+    background::SnapshotPin,
+    entry::{self, Lookup},
+    error::Result,
+    iterator::MergeIterator,
+    merge::Merger,
+};
+
+/// `Snapshot` struct is a read-only view of an `LsmTree` as it was at a point in time, taken with `LsmTree::snapshot`.
+///
+/// It is pinned to the sequence number of the last write made before it was taken, and ignores every newer write,
+/// so reading several keys, or scanning the table, through it gives a consistent view while the table goes on changing.
+/// It reads the memtables and the SSTables of the table at its sequence number,
+/// and the writes and compactions keep the versions of the keys it can see until it is dropped.
+pub struct Snapshot {
+    pin: SnapshotPin,
+    merger: Arc<Merger>,
+}
+
+impl Snapshot {
+    /// Creates a new `Snapshot`.
+    ///
+    /// # Arguments
+    ///
+    /// * `pin` - The registration of the snapshot with the background work of the table.
+    /// * `merger` - The `Merger` of the table, folding merge operands.
+    pub fn new(pin: SnapshotPin, merger: Arc<Merger>) -> Self {
+        Self { pin, merger }
+    }
+
+    /// Returns the sequence number of the last write seen by the snapshot.
+    pub fn get_sequence(&self) -> u64 {
+        self.pin.get_sequence()
+    }
+
+    /// Retrieve the row associated with a given key as it was when the snapshot was taken.
+    /// Returns `None` if the key was absent or deleted then.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A byte slice that holds the key.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.pin.get_version().get(key, self.get_sequence())? {
+            Lookup::Found { value, .. } => Ok(Some(value)),
+            Lookup::Deleted { .. } | Lookup::Absent => Ok(None),
+            Lookup::Merge { .. } => Ok(self.scan(key..=key)?.next().transpose()?.map(|(_, row)| row)),
+        }
+    }
+
+    /// Returns an iterator over the keys live when the snapshot was taken and their rows, in key order.
+    pub fn iter(&self) -> Result<MergeIterator<'_>> {
+        self.scan(..)
+    }
+
+    /// Returns an iterator over the keys within a range live when the snapshot was taken and their rows, in key order.
+    ///
+    /// # Arguments
+    ///
+    /// * `range` - The range of the keys, such as `start..end` or `start..`.
+    pub fn scan<'r>(&self, range: impl RangeBounds<&'r [u8]>) -> Result<MergeIterator<'_>> {
+        let start = range.start_bound().map(|key| *key);
+        let end = range.end_bound().map(|key| *key);
+        // An inverted range holds no key
+        if entry::is_empty_range(start, end) {
+            return Ok(MergeIterator::new(Vec::new(), false));
+        }
+
+        let version = self.pin.get_version();
+        let sources = version.sources(start, end, false, self.get_sequence())?;
+        let range_tombstones = version.range_tombstones(self.get_sequence());
+
+        Ok(MergeIterator::new(sources, false).with_merger(Arc::clone(&self.merger)).with_range_tombstones(range_tombstones))
+    }
+
+    /// Returns an iterator over the keys within a range live when the snapshot was taken and their rows, in reverse key order.
+    ///
+    /// # Arguments
+    ///
+    /// * `range` - The range of the keys, such as `start..end` or `..end`.
+    pub fn scan_rev<'r>(&self, range: impl RangeBounds<&'r [u8]>) -> Result<MergeIterator<'_>> {
+        let start = range.start_bound().map(|key| *key);
+        let end = range.end_bound().map(|key| *key);
+        // An inverted range holds no key
+        if entry::is_empty_range(start, end) {
+            return Ok(MergeIterator::new(Vec::new(), true));
+        }
+
+        let version = self.pin.get_version();
+        let sources = version.sources(start, end, true, self.get_sequence())?;
+        let range_tombstones = version.range_tombstones(self.get_sequence());
+
+        Ok(MergeIterator::new(sources, true).with_merger(Arc::clone(&self.merger)).with_range_tombstones(range_tombstones))
+    }
+}
+
+impl std::fmt::Debug for Snapshot {
+    /// Formats the `Snapshot` for printing.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Snapshot").field("sequence", &self.get_sequence()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::lsm_tree::{
+        column::{Column, DataType},
+        merge::AddInt,
+        options::Options,
+        tree::LsmTree,
+    };
+
+    fn create(path: &std::path::Path) -> LsmTree {
+        LsmTree::create(path.join("table").to_string_lossy().into_owned(), vec![Column::new("Name", DataType::Text)], Options::default()).unwrap()
+    }
+
+    #[test]
+    fn ignores_later_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut table = create(dir.path());
+        table.insert(b"a", &[b"old".to_vec()]).unwrap();
+        table.insert(b"b", &[b"old".to_vec()]).unwrap();
+
+        let snapshot = table.snapshot();
+        table.insert(b"a", &[b"new".to_vec()]).unwrap();
+        table.delete(b"b").unwrap();
+        table.insert(b"c", &[b"new".to_vec()]).unwrap();
+
+        assert_eq!(table.decode(&snapshot.get(b"a").unwrap().unwrap()).unwrap()["Name"].get_text(), "old");
+        assert!(snapshot.get(b"b").unwrap().is_some());
+        assert!(snapshot.get(b"c").unwrap().is_none());
+        let keys: Vec<Vec<u8>> = snapshot.iter().unwrap().map(|item| item.unwrap().0).collect();
+        assert_eq!(keys, vec![b"a".to_vec(), b"b".to_vec()]);
+    }
+
+    #[test]
+    fn survives_flush_compaction_and_clear() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut table = create(dir.path());
+        for i in 0..50 {
+            table.insert(format!("k{:04}", i).as_bytes(), &[b"value".to_vec()]).unwrap();
+        }
+        table.flush().unwrap();
+
+        let snapshot = table.snapshot();
+        for i in 0..50 {
+            table.insert(format!("k{:04}", i).as_bytes(), &[b"newer".to_vec()]).unwrap();
+        }
+        table.flush().unwrap();
+        table.wait_for_compaction().unwrap();
+        table.clear().unwrap();
+
+        assert!(snapshot.get(b"k0001").unwrap().is_some());
+        assert_eq!(snapshot.iter().unwrap().count(), 50);
+        assert!(snapshot.iter().unwrap().all(|item| table.decode(&item.unwrap().1).unwrap()["Name"].get_text() == "value"));
+        assert_eq!(table.iter().unwrap().count(), 0);
+    }
+
+    #[test]
+    fn sees_each_its_own_point_in_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let columns = vec![Column::new("Name", DataType::Text), Column::new("Count", DataType::Int)];
+        let options = Options { merge_operator: Some(Arc::new(AddInt::new("Count"))), ..Default::default() };
+        let mut table = LsmTree::create(dir.path().join("table").to_string_lossy().into_owned(), columns, options).unwrap();
+        for key in [b"a", b"b", b"c", b"d"] {
+            table.insert(key, &[key.to_vec(), 1_i32.to_ne_bytes().to_vec()]).unwrap();
+        }
+        table.flush().unwrap();
+
+        let first = table.snapshot();
+        table.merge(b"a", &10_i32.to_ne_bytes()).unwrap();
+        table.delete_range(b"b", b"d").unwrap();
+        let second = table.snapshot();
+        table.merge(b"a", &100_i32.to_ne_bytes()).unwrap();
+        table.insert(b"c", &[b"c".to_vec(), 5_i32.to_ne_bytes().to_vec()]).unwrap();
+        table.flush().unwrap();
+        table.wait_for_compaction().unwrap();
+
+        let counts = |items: Vec<(Vec<u8>, Vec<u8>)>| -> Vec<(Vec<u8>, i32)> { items.into_iter().map(|(key, row)| (key, table.decode(&row).unwrap()["Count"].get_int())).collect() };
+        let forward = |snapshot: &super::Snapshot| counts(snapshot.scan(b"a".as_slice()..b"d".as_slice()).unwrap().map(Result::unwrap).collect());
+        let backward = |snapshot: &super::Snapshot| counts(snapshot.scan_rev(b"a".as_slice()..=b"d".as_slice()).unwrap().map(Result::unwrap).collect());
+
+        assert_eq!(forward(&first), vec![(b"a".to_vec(), 1), (b"b".to_vec(), 1), (b"c".to_vec(), 1)]);
+        assert_eq!(forward(&second), vec![(b"a".to_vec(), 11)]);
+        assert_eq!(backward(&second), vec![(b"d".to_vec(), 1), (b"a".to_vec(), 11)]);
+        assert_eq!(counts(table.iter().unwrap().map(Result::unwrap).collect()), vec![(b"a".to_vec(), 111), (b"c".to_vec(), 5), (b"d".to_vec(), 1)]);
+        assert!(second.get(b"c").unwrap().is_none() && first.get(b"c").unwrap().is_some());
+    }
+
+    #[test]
+    fn reads_the_memtable_it_shares_with_the_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let columns = vec![Column::new("Name", DataType::Text), Column::new("Count", DataType::Int)];
+        let options = Options { merge_operator: Some(Arc::new(AddInt::new("Count"))), memtable_size: 4096, ..Default::default() };
+        let mut table = LsmTree::create(dir.path().join("table").to_string_lossy().into_owned(), columns, options).unwrap();
+        table.insert(b"a", &[b"a".to_vec(), 1_i32.to_ne_bytes().to_vec()]).unwrap();
+
+        // Every write stays in the memtable, each snapshot sees the operands written before it
+        let count = |table: &LsmTree, row: Option<Vec<u8>>| row.map(|row| table.decode(&row).unwrap()["Count"].get_int());
+        let first = table.snapshot();
+        table.merge(b"a", &10_i32.to_ne_bytes()).unwrap();
+        let second = table.snapshot();
+        table.merge(b"a", &100_i32.to_ne_bytes()).unwrap();
+        table.clear().unwrap();
+        let third = table.snapshot();
+        table.insert(b"a", &[b"a".to_vec(), 1000_i32.to_ne_bytes().to_vec()]).unwrap();
+        table.clear().unwrap();
+
+        assert_eq!(count(&table, first.get(b"a").unwrap()), Some(1));
+        assert_eq!(count(&table, second.get(b"a").unwrap()), Some(11));
+        assert_eq!(third.get(b"a").unwrap(), None);
+        assert_eq!(table.get(b"a").unwrap(), None);
+        drop((first, second, third));
+        table.insert(b"a", &[b"a".to_vec(), 5_i32.to_ne_bytes().to_vec()]).unwrap();
+        assert_eq!(count(&table, table.snapshot().get(b"a").unwrap()), Some(5));
+    }
+}
@@ -1,222 +1,675 @@
-use bloomfilter::Bloom;
 use std::{
-    collections::BTreeMap,
     fmt::Debug,
-    io::{Read, Write},
+    fs::{self, File},
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    ops::{Bound, Range},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
-use super::{entry::Entry, memtable::Memtable};
+use bloomfilter::Bloom;
 
+use super::{
+    entry::{self, Entry, EntryKind, Lookup},
+    error::{CopperError, Result},
+    manifest,
+    memtable::Memtable,
+    options::Options,
+};
+
+/// Magic number closing every SSTable file.
+const MAGIC: u32 = u32::from_be_bytes(*b"CPSS");
+
+/// Version of the SSTable file layout.
+const VERSION: u32 = 1;
+
+/// Size of the footer: range tombstone offset, range tombstone size, filter offset, filter size, index offset, index size,
+/// entry count, data size, highest sequence number, version and magic.
+const FOOTER_SIZE: u64 = 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 4 + 4;
+
+/// `SSTable` struct represents a sorted, immutable table stored in a file.
+///
+/// The file is a sequence of data blocks of about `block_size` bytes, followed by a range tombstone block, a filter block, an index block and a footer.
+/// Each block ends with the CRC32 of its content.
+/// Only the range tombstones, the bloom filter of the keys and the index, holding the first key and the position of every data block, are kept in memory,
+/// so a point lookup reads at most a single data block.
+/// The keys of an `SSTable` span its entries and its range tombstones.
+///
+/// SSTables are shared with `Arc`s, a file merged by a compaction is marked obsolete
+/// and only deleted once the last reader going through it drops it.
 pub struct SSTable {
-    data: BTreeMap<Vec<u8>, Entry>,
-    bloom_filter: Bloom<Vec<u8>>,
+    path: PathBuf,
+    filter: Option<Bloom<[u8]>>,
+    index: Vec<BlockHandle>,
+    range_tombstones: Vec<Entry>,
+    last_key: Vec<u8>,
+    entry_count: u64,
+    data_size: u64,
+    max_sequence: u64,
+    obsolete: AtomicBool,
+}
+
+/// `BlockHandle` struct locates a data block in an SSTable file and holds its first key.
+struct BlockHandle {
+    first_key: Vec<u8>,
+    offset: u64,
+    size: u32,
 }
 
 impl SSTable {
-    /// Returns the value of the entry with the given key if it exists and the bloom filter indicates that it might be in the `SSTable`.
+    /// Looks up the given key in the `SSTable`.
     ///
     /// # Arguments
     ///
     /// * `key` - A byte slice that holds the key of the entry.
-    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
-        // Check the Bloom filter first
-        if !self.bloom_filter.check(&key.to_vec()) {
-            return None;
+    ///
+    /// Returns the newest version of the key, `Lookup::Deleted` if it is a tombstone or if a newer range tombstone covers it.
+    /// Returns early if the bloom filter tells the key is not in the `SSTable`,
+    /// otherwise only the data block that may hold the key is read.
+    pub fn get(&self, key: &[u8]) -> Result<Lookup> {
+        self.get_at(key, u64::MAX)
+    }
+
+    /// Looks up the given key in the `SSTable` as it was after the write with the given sequence number.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A byte slice that holds the key of the entry.
+    /// * `sequence` - The sequence number of the last write to see.
+    ///
+    /// Returns the newest version of the key that is not newer than the sequence number, see `get`.
+    /// An `SSTable` holds several versions of a key when a compaction keeps the ones a `Snapshot` can see.
+    pub fn get_at(&self, key: &[u8], sequence: u64) -> Result<Lookup> {
+        let tombstone = entry::newest_covering(&self.range_tombstones, key, sequence);
+        if !self.may_contain(key) {
+            return Ok(Lookup::Absent.covered_by(tombstone));
         }
 
-        // Look up the key in the data map
-        self.data.get(key).map(|entry| entry.get_value().to_vec())
+        // Find the last block starting at or before the key
+        let Some(block) = self.index.partition_point(|handle| handle.first_key.as_slice() <= key).checked_sub(1) else {
+            return Ok(Lookup::Absent.covered_by(tombstone));
+        };
+
+        // The versions of a key are all in the same data block, the newest first
+        let entries = self.read_block(&mut File::open(&self.path)?, block)?;
+        let first = entries.partition_point(|entry| entry.get_key() < key);
+        Ok(Lookup::from(entries[first..].iter().take_while(|entry| entry.get_key() == key).find(|entry| entry.get_sequence() <= sequence)).covered_by(tombstone))
     }
 
-    /// Creates a new `SSTable` from a `Memtable`.
+    /// Creates a new `SSTable` file from a `Memtable`.
     ///
     /// # Arguments
     ///
     /// * `memtable` - A reference to a `Memtable` that holds the entries to be included in the `SSTable`.
+    /// * `path` - The path of the file to create.
+    /// * `options` - The `Options` of the table, giving the size of the data blocks and of the bloom filter.
+    pub fn from_memtable(memtable: &Memtable, path: &Path, options: &Options) -> Result<Self> {
+        Self::create(path, memtable.get_all_entries(), memtable.get_range_tombstones().to_vec(), options)
+    }
+
+    /// Writes entries to a new `SSTable` file and opens it.
     ///
-    /// The function creates a new bloom filter and a new `BTreeMap` of entries,
-    /// and inserts all entries from the `Memtable` into the `BTreeMap` and the bloom filter.
-    pub fn from_memtable(memtable: &Memtable) -> Self {
-        let mut bloom_filter = Bloom::new_for_fp_rate(1000, 0.01);
-        let mut data = BTreeMap::new();
-        for key in memtable.get_entries().keys() {
-            let entry = memtable.get(key).unwrap();
-            data.insert(key.to_vec(), entry);
-            bloom_filter.set(key);
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file to create.
+    /// * `entries` - The entries of the table, sorted by key, the versions of a key the newest first.
+    /// * `range_tombstones` - The range tombstones of the table.
+    /// * `options` - The `Options` of the table, giving the size of the data blocks and of the bloom filter.
+    ///
+    /// Entries are appended to the current data block, which is written once it reaches `block_size`,
+    /// but never between two versions of a key.
+    /// The bloom filter is sized from the number of keys with `bits_per_key` bits per key.
+    /// The file is written next to its final path and renamed once complete and synced,
+    /// so a crash never leaves a partial `SSTable` behind.
+    /// The directory is synced after the rename, so the file survives a crash once the MANIFEST lists it.
+    pub fn create(path: &Path, entries: impl IntoIterator<Item = Entry>, range_tombstones: Vec<Entry>, options: &Options) -> Result<Self> {
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+
+        let mut index = Vec::new();
+        let mut last_key = Vec::new();
+        let mut entry_count: u64 = 0;
+        let mut data_size: u64 = 0;
+        let mut max_sequence = 0;
+        let mut offset = 0;
+        let mut block = Vec::new();
+        let mut first_key = Vec::new();
+        let mut keys = Vec::new();
+
+        for entry in entries {
+            if block.len() >= options.block_size && entry.get_key() != last_key.as_slice() {
+                index.push(write_block(&mut writer, &mut offset, std::mem::take(&mut first_key), &block)?);
+                block.clear();
+            }
+            if block.is_empty() {
+                first_key = entry.get_key().to_vec();
+            }
+
+            encode_entry(&mut block, &entry);
+
+            entry_count += 1;
+            max_sequence = max_sequence.max(entry.get_sequence());
+            data_size += entry.get_size() as u64;
+            if entry.get_key() != last_key.as_slice() || keys.is_empty() {
+                last_key = entry.get_key().to_vec();
+                keys.push(last_key.clone());
+            }
+        }
+        if !block.is_empty() {
+            index.push(write_block(&mut writer, &mut offset, first_key, &block)?);
         }
 
-        Self { data, bloom_filter }
+        // Write the range tombstone block, encoded like a data block
+        let mut range_block = Vec::new();
+        for tombstone in &range_tombstones {
+            encode_entry(&mut range_block, tombstone);
+            max_sequence = max_sequence.max(tombstone.get_sequence());
+        }
+        let range_offset = offset;
+        write_block(&mut writer, &mut offset, Vec::new(), &range_block)?;
+
+        // Write the filter block, a table without keys or with filters disabled has an empty one
+        let filter = build_filter(&keys, options.bits_per_key);
+        let mut filter_block = Vec::new();
+        if let Some(filter) = &filter {
+            filter_block.extend_from_slice(&filter.number_of_bits().to_be_bytes());
+            filter_block.extend_from_slice(&filter.number_of_hash_functions().to_be_bytes());
+            for (k0, k1) in filter.sip_keys() {
+                filter_block.extend_from_slice(&k0.to_be_bytes());
+                filter_block.extend_from_slice(&k1.to_be_bytes());
+            }
+            filter_block.extend_from_slice(&filter.bitmap());
+        }
+        let filter_offset = offset;
+        write_block(&mut writer, &mut offset, Vec::new(), &filter_block)?;
+
+        // Write the index block: the handle of every data block, then the last key of the table
+        let mut index_block = Vec::new();
+        index_block.extend_from_slice(&(index.len() as u32).to_be_bytes());
+        for handle in &index {
+            index_block.extend_from_slice(&(handle.first_key.len() as u32).to_be_bytes());
+            index_block.extend_from_slice(&handle.first_key);
+            index_block.extend_from_slice(&handle.offset.to_be_bytes());
+            index_block.extend_from_slice(&handle.size.to_be_bytes());
+        }
+        index_block.extend_from_slice(&(last_key.len() as u32).to_be_bytes());
+        index_block.extend_from_slice(&last_key);
+        let index_offset = offset;
+        write_block(&mut writer, &mut offset, Vec::new(), &index_block)?;
+
+        // Write the footer
+        writer.write_all(&range_offset.to_be_bytes())?;
+        writer.write_all(&(range_block.len() as u64).to_be_bytes())?;
+        writer.write_all(&filter_offset.to_be_bytes())?;
+        writer.write_all(&(filter_block.len() as u64).to_be_bytes())?;
+        writer.write_all(&index_offset.to_be_bytes())?;
+        writer.write_all(&(index_block.len() as u64).to_be_bytes())?;
+        writer.write_all(&entry_count.to_be_bytes())?;
+        writer.write_all(&data_size.to_be_bytes())?;
+        writer.write_all(&max_sequence.to_be_bytes())?;
+        writer.write_all(&VERSION.to_be_bytes())?;
+        writer.write_all(&MAGIC.to_be_bytes())?;
+
+        let file = writer.into_inner().map_err(|err| err.into_error())?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        manifest::sync_dir(path.parent().unwrap_or(Path::new(".")))?;
+
+        Ok(Self { path: path.to_path_buf(), filter, index, range_tombstones, last_key, entry_count, data_size, max_sequence, obsolete: AtomicBool::new(false) })
     }
 
-    /// Saves the `SSTable` to a file on disk.
+    /// Opens an `SSTable` file.
     ///
     /// # Arguments
     ///
-    /// * `path` - A string slice that specifies the path of the file.
+    /// * `path` - The path of the file.
     ///
-    /// The function creates a new file at the specified path,
-    /// and writes the number of entries in the `SSTable` and each key-value pair to the file.
-    pub fn save_to_disk(&self, path: &str) -> Result<(), std::io::Error> {
-        let file = std::fs::File::create(path)?;
-        let mut writer = std::io::BufWriter::new(file);
+    /// Only the footer, the range tombstone block, the filter block and the index block are read.
+    /// Returns a `Corruption` error if the footer, the range tombstones, the filter or the index cannot be read back.
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let file_size = file.metadata()?.len();
+        let corruption = |offset| CopperError::corruption(path, offset);
+
+        // Read the footer, closed by the version and the magic number
+        let footer_offset = file_size.checked_sub(FOOTER_SIZE).ok_or_else(|| corruption(0))?;
+        let mut footer = vec![0; FOOTER_SIZE as usize];
+        file.seek(SeekFrom::Start(footer_offset))?;
+        file.read_exact(&mut footer)?;
+        let mut reader = Reader { path, data: &footer, offset: 0, base: footer_offset };
+        let range_offset = reader.read_u64()?;
+        let range_size = reader.read_u64()?;
+        let filter_offset = reader.read_u64()?;
+        let filter_size = reader.read_u64()?;
+        let index_offset = reader.read_u64()?;
+        let index_size = reader.read_u64()?;
+        let entry_count = reader.read_u64()?;
+        let data_size = reader.read_u64()?;
+        let max_sequence = reader.read_u64()?;
+        if reader.read_u32()? != VERSION || reader.read_u32()? != MAGIC {
+            return Err(corruption(file_size - 8));
+        }
+        if index_size.checked_add(4).and_then(|size| index_offset.checked_add(size)) != Some(footer_offset) {
+            return Err(corruption(footer_offset));
+        }
 
-        // Write the number of entries in the SSTable
-        writer.write_all(&(self.data.len() as u64).to_be_bytes())?;
+        // Read the range tombstone block
+        let mut range_tombstones = Vec::new();
+        if range_size > 0 {
+            let range_block = read_checked(&mut file, path, range_offset, range_size as u32)?;
+            range_tombstones = decode_entries(path, &range_block, range_offset)?;
+        }
 
-        // Write each key-value pair to the file
-        for (key, entry) in &self.data {
-            writer.write_all(&(key.len() as u64).to_be_bytes())?;
-            writer.write_all(key)?;
-            writer.write_all(&(entry.get_value().len() as u64).to_be_bytes())?;
-            writer.write_all(entry.get_value())?;
-            // Write the tombstone
-            writer.write_all(&[if entry.is_deleted() { 1 } else { 0 }])?;
+        // Read the filter block
+        let mut filter = None;
+        if filter_size > 0 {
+            let filter_block = read_checked(&mut file, path, filter_offset, filter_size as u32)?;
+            let mut reader = Reader { path, data: &filter_block, offset: 0, base: filter_offset };
+            let bits = reader.read_u64()?;
+            let hash_functions = reader.read_u32()?;
+            let sip_keys = [(reader.read_u64()?, reader.read_u64()?), (reader.read_u64()?, reader.read_u64()?)];
+            let bitmap = reader.read(bits.div_ceil(8) as usize)?;
+            filter = Some(Bloom::from_existing(bitmap, bits, hash_functions, sip_keys));
         }
 
-        Ok(())
+        // Read the index block
+        let index_block = read_checked(&mut file, path, index_offset, index_size as u32)?;
+        let mut reader = Reader { path, data: &index_block, offset: 0, base: index_offset };
+        let block_count = reader.read_u32()?;
+        let mut index = Vec::new();
+        for _ in 0..block_count {
+            let key_len = reader.read_u32()? as usize;
+            let first_key = reader.read(key_len)?.to_vec();
+            let offset = reader.read_u64()?;
+            let size = reader.read_u32()?;
+            index.push(BlockHandle { first_key, offset, size });
+        }
+        let key_len = reader.read_u32()? as usize;
+        let last_key = reader.read(key_len)?.to_vec();
+
+        Ok(Self { path: path.to_path_buf(), filter, index, range_tombstones, last_key, entry_count, data_size, max_sequence, obsolete: AtomicBool::new(false) })
+    }
+
+    /// Returns whether the key may be in the `SSTable`.
+    /// A `false` is certain, a `true` can be a false positive of the bloom filter.
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        self.filter.as_ref().is_none_or(|filter| filter.check(key))
+    }
+
+    /// Returns all entries in the `SSTable`, reading every data block.
+    pub fn get_all_entries(&self) -> Result<Vec<Entry>> {
+        let mut file = File::open(&self.path)?;
+        let mut entries = Vec::new();
+        for block in 0..self.index.len() {
+            entries.extend(self.read_block(&mut file, block)?);
+        }
+
+        Ok(entries)
     }
 
-    /// Saves the `SSTable` to a file on disk.
+    /// Returns an iterator over the entries of the `SSTable` in key order, tombstones included.
+    /// Data blocks are read one at a time, as the iterator reaches them.
+    pub fn iter(self: &Arc<Self>) -> Result<SSTableIterator> {
+        self.range(Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// Returns an iterator over the entries of the `SSTable` between two bounds in key order, tombstones included.
     ///
     /// # Arguments
     ///
-    /// * `path` - A string slice that specifies the path of the file.
+    /// * `start` - The lower bound of the keys.
+    /// * `end` - The upper bound of the keys.
     ///
-    /// The function creates a new file at the specified path,
-    /// and writes the number of entries in the `SSTable` and each key-value pair to the file.
-    pub fn get_all_entries(&self) -> Vec<Entry> {
-        self.data.iter().map(|(key, entry)| Entry::new(key, entry.get_value(), entry.is_deleted())).collect()
+    /// The iterator starts at the data block that may hold the lower bound, found with the index,
+    /// and stops at the first key past the upper bound.
+    pub fn range(self: &Arc<Self>, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<SSTableIterator> {
+        let first = match start {
+            Bound::Included(key) | Bound::Excluded(key) => self.index.partition_point(|handle| handle.first_key.as_slice() <= key).saturating_sub(1),
+            Bound::Unbounded => 0,
+        };
+
+        self.iterator(first..self.index.len(), start, end, false)
     }
 
-    /// Loads an `SSTable` from a file on disk.
+    /// Returns an iterator over the entries of the `SSTable` between two bounds in reverse key order, tombstones included.
     ///
     /// # Arguments
     ///
-    /// * `path` - A string slice that specifies the path of the file.
+    /// * `start` - The lower bound of the keys.
+    /// * `end` - The upper bound of the keys.
     ///
-    /// The function opens the file at the specified path,
-    /// reads the number of entries in the `SSTable` and each key-value pair from the file,
-    /// and creates a new `SSTable` with the read entries and a new bloom filter.
-    pub fn load_from_disk(path: &str) -> Result<Self, std::io::Error> {
-        let file = std::fs::File::open(path)?;
-        let mut reader = std::io::BufReader::new(file);
+    /// The iterator starts at the data block that may hold the upper bound, found with the index,
+    /// and stops at the first key before the lower bound.
+    pub fn range_rev(self: &Arc<Self>, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<SSTableIterator> {
+        let last = match end {
+            Bound::Included(key) | Bound::Excluded(key) => self.index.partition_point(|handle| handle.first_key.as_slice() <= key),
+            Bound::Unbounded => self.index.len(),
+        };
+
+        self.iterator(0..last, start, end, true)
+    }
 
-        // Read the number of entries in the SSTable
-        let mut num_entries = [0; 8];
-        reader.read_exact(&mut num_entries)?;
-        let num_entries = u64::from_be_bytes(num_entries) as usize;
+    /// Returns an iterator over the given data blocks, keeping the entries between two bounds.
+    fn iterator(self: &Arc<Self>, blocks: Range<usize>, start: Bound<&[u8]>, end: Bound<&[u8]>, reverse: bool) -> Result<SSTableIterator> {
+        Ok(SSTableIterator { sstable: Arc::clone(self), file: File::open(&self.path)?, blocks, entries: Vec::new().into_iter(), start: start.map(<[u8]>::to_vec), end: end.map(<[u8]>::to_vec), reverse })
+    }
 
-        // Read each key-value pair from the file
-        let mut data = BTreeMap::new();
-        for _ in 0..num_entries {
-            let mut key_len = [0; 8];
-            reader.read_exact(&mut key_len)?;
-            let key_len = u64::from_be_bytes(key_len) as usize;
+    /// Returns the size of the `SSTable`, which is the sum of the lengths of all keys, values, and tombstones.
+    pub fn get_size(&self) -> usize {
+        self.data_size as usize
+    }
 
-            let mut key = vec![0; key_len];
-            reader.read_exact(&mut key)?;
+    /// Returns the range tombstones of the `SSTable`.
+    pub fn get_range_tombstones(&self) -> &[Entry] {
+        &self.range_tombstones
+    }
 
-            let mut value_len = [0; 8];
-            reader.read_exact(&mut value_len)?;
-            let value_len = u64::from_be_bytes(value_len) as usize;
+    /// Returns whether the `SSTable` holds no entry and no range tombstone.
+    pub fn is_empty(&self) -> bool {
+        self.entry_count == 0 && self.range_tombstones.is_empty()
+    }
 
-            let mut value = vec![0; value_len];
-            reader.read_exact(&mut value)?;
+    /// Returns the highest sequence number of the entries and range tombstones of the `SSTable`.
+    pub fn get_max_sequence(&self) -> u64 {
+        self.max_sequence
+    }
 
-            // Read the tombstone
-            let mut tombstone = [0];
-            reader.read_exact(&mut tombstone)?;
+    /// Returns the path of the `SSTable` file.
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
 
-            let entry = Entry::new(&key, &value, tombstone[0] == 1);
-            data.insert(key, entry);
-        }
+    /// Marks the file of the `SSTable` as no longer part of the table, it is deleted when the `SSTable` is dropped.
+    pub fn mark_obsolete(&self) {
+        self.obsolete.store(true, Ordering::Release);
+    }
 
-        let mut bloom_filter = Bloom::new_for_fp_rate(1000, 0.01);
-        for key in data.keys() {
-            bloom_filter.set(key);
-        }
+    /// Returns the smallest key of the `SSTable`, the first key of its first data block or the start of a range tombstone.
+    pub fn get_first_key(&self) -> &[u8] {
+        let first_key = self.index.first().map(|block| block.first_key.as_slice());
+        self.range_tombstones.iter().map(Entry::get_key).chain(first_key).min().unwrap_or_default()
+    }
 
-        Ok(Self { data, bloom_filter })
+    /// Returns the largest key of the `SSTable`, its last key or the end of a range tombstone.
+    pub fn get_last_key(&self) -> &[u8] {
+        let last_key = (self.entry_count > 0).then_some(self.last_key.as_slice());
+        self.range_tombstones.iter().map(Entry::get_value).chain(last_key).max().unwrap_or_default()
     }
 
-    /// Returns the size of the `SSTable`, which is the sum of the lengths of all keys, values, and tombstones.
-    pub fn get_size(&self) -> usize {
-        let mut sum = 0;
-        for (key, entry) in &self.data {
-            sum += key.len() + entry.get_value().len() + 1;
-        }
-        sum
+    /// Returns whether the keys of the `SSTable` overlap the given range of keys, both ends inclusive.
+    /// An empty `SSTable` overlaps nothing.
+    pub fn overlaps(&self, first_key: &[u8], last_key: &[u8]) -> bool {
+        !self.is_empty() && self.get_first_key() <= last_key && first_key <= self.get_last_key()
     }
 
-    /// Compacts a level of `SSTable`s into a single `SSTable`.
-    ///
-    /// # Arguments
-    ///
-    /// * `level` - A reference to a vector of `SSTable`s that represents the level to be compacted.
-    ///
-    /// The function creates a new `SSTable` and a new bloom filter,
-    /// and inserts all entries from the `SSTable`s in the level into the `SSTable` and the bloom filter.
-    pub fn compact(level: &Vec<Self>) -> Self {
-        let mut data = BTreeMap::new();
-        let mut bloom_filter = Bloom::new_for_fp_rate(1000, 0.01);
+    /// Reads the data block at position `block` of the index and decodes its entries.
+    fn read_block(&self, file: &mut File, block: usize) -> Result<Vec<Entry>> {
+        let handle = &self.index[block];
+        let data = read_checked(file, &self.path, handle.offset, handle.size)?;
+        decode_entries(&self.path, &data, handle.offset)
+    }
+}
 
-        for sstable in level {
-            for (key, entry) in &sstable.data {
-                data.insert(key.to_vec(), Entry::new(key, entry.get_value(), entry.is_deleted()));
-                bloom_filter.set(key);
+/// `SSTableIterator` struct iterates over the entries of an `SSTable` between two bounds in key order, or in reverse key order,
+/// holding only the data block it is in.
+pub struct SSTableIterator {
+    sstable: Arc<SSTable>,
+    file: File,
+    blocks: Range<usize>,
+    entries: std::vec::IntoIter<Entry>,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    reverse: bool,
+}
+
+impl Iterator for SSTableIterator {
+    type Item = Result<Entry>;
+
+    /// Returns the next entry, reading the next data block when the current one is exhausted.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                // The first data block read can hold keys outside of the bounds on the side the iteration starts from,
+                // the first key outside of the bounds on the other side ends the iteration
+                let (skip, stop) = (!self.after_start(entry.get_key()), !self.before_end(entry.get_key()));
+                let (skip, stop) = if self.reverse { (stop, skip) } else { (skip, stop) };
+                if skip {
+                    continue;
+                }
+                if stop {
+                    self.finish();
+                    return None;
+                }
+
+                return Some(Ok(entry));
+            }
+
+            let block = if self.reverse { self.blocks.next_back() } else { self.blocks.next() };
+            let block = block?;
+
+            // Do not read a data block starting past the upper bound
+            if !self.reverse && !self.before_end(&self.sstable.index[block].first_key) {
+                self.finish();
+                return None;
+            }
+
+            match self.sstable.read_block(&mut self.file, block) {
+                Ok(mut entries) => {
+                    if self.reverse {
+                        entries.reverse();
+                    }
+                    self.entries = entries.into_iter();
+                }
+                Err(err) => {
+                    // Stop after reporting the error
+                    self.finish();
+                    return Some(Err(err));
+                }
             }
         }
+    }
+}
 
-        Self { data, bloom_filter }
+impl SSTableIterator {
+    /// Returns whether the key is after the lower bound.
+    fn after_start(&self, key: &[u8]) -> bool {
+        match &self.start {
+            Bound::Included(start) => key >= start.as_slice(),
+            Bound::Excluded(start) => key > start.as_slice(),
+            Bound::Unbounded => true,
+        }
     }
 
-    /// Returns a range of entries in the `SSTable` from the start key to the end key, inclusive.
-    ///
-    /// # Arguments
-    ///
-    /// * `start` - A byte slice that holds the start key of the range.
-    /// * `end` - A byte slice that holds the end key of the range.
-    ///
-    /// The function does not return deleted entries.
-    pub fn get_range(&self, start: &[u8], end: &[u8]) -> Vec<Entry> {
-        let start_key = start.to_vec();
-        let end_key = end.to_vec();
+    /// Returns whether the key is before the upper bound.
+    fn before_end(&self, key: &[u8]) -> bool {
+        match &self.end {
+            Bound::Included(end) => key <= end.as_slice(),
+            Bound::Excluded(end) => key < end.as_slice(),
+            Bound::Unbounded => true,
+        }
+    }
 
-        self.data.range(start_key..=end_key).filter_map(|(key, entry)| if entry.is_deleted() { None } else { Some(Entry::new(key, entry.get_value(), entry.is_deleted())) }).collect()
+    /// Ends the iteration.
+    fn finish(&mut self) {
+        self.blocks = 0..0;
+        self.entries = Vec::new().into_iter();
     }
+}
 
-    /// Clears the `SSTable`, removing all entries and clearing the bloom filter.
-    pub fn clear(&mut self) {
-        self.data.clear();
-        self.bloom_filter.clear();
+/// Builds a bloom filter holding the keys with `bits_per_key` bits per key.
+/// Returns `None` if there are no keys or filters are disabled with 0 bits per key.
+fn build_filter(keys: &[Vec<u8>], bits_per_key: usize) -> Option<Bloom<[u8]>> {
+    if keys.is_empty() || bits_per_key == 0 {
+        return None;
     }
 
-    /// Merges multiple `SSTable`s into a single `SSTable`.
-    ///
-    /// # Arguments
-    ///
-    /// * `sstables` - A slice of `SSTable`s that are to be merged.
-    ///
-    /// The function creates a new `SSTable` and a new bloom filter,
-    /// and inserts all entries from the `SSTable`s into the `SSTable` and the bloom filter.
-    pub fn merge(sstables: &[SSTable]) -> SSTable {
-        let mut data = BTreeMap::new();
-        let mut bloom_filter = Bloom::new_for_fp_rate(1000, 0.01);
+    let mut filter = Bloom::new((keys.len() * bits_per_key).div_ceil(8), keys.len());
+    for key in keys {
+        filter.set(key.as_slice());
+    }
+    Some(filter)
+}
 
-        for sstable in sstables {
-            for (key, entry) in &sstable.data {
-                data.insert(key.to_vec(), Entry::new(key, entry.get_value(), entry.is_deleted()));
-                bloom_filter.set(key);
-            }
-        }
+/// Appends an entry to a data block or to the range tombstone block:
+/// its key and its value, each prefixed with its length as a big endian `u32`, its kind and its sequence number.
+fn encode_entry(block: &mut Vec<u8>, entry: &Entry) {
+    block.extend_from_slice(&(entry.get_key().len() as u32).to_be_bytes());
+    block.extend_from_slice(entry.get_key());
+    block.extend_from_slice(&(entry.get_value().len() as u32).to_be_bytes());
+    block.extend_from_slice(entry.get_value());
+    block.push(entry.get_kind() as u8);
+    block.extend_from_slice(&entry.get_sequence().to_be_bytes());
+}
+
+/// Decodes the entries of a data block or of the range tombstone block read at `offset` in a file.
+fn decode_entries(path: &Path, data: &[u8], offset: u64) -> Result<Vec<Entry>> {
+    let mut reader = Reader { path, data, offset: 0, base: offset };
+
+    let mut entries = Vec::new();
+    while reader.offset < data.len() {
+        let key_len = reader.read_u32()? as usize;
+        let key = reader.read(key_len)?;
+        let value_len = reader.read_u32()? as usize;
+        let value = reader.read(value_len)?;
+        let kind = EntryKind::from_byte(reader.read(1)?[0]).ok_or_else(|| CopperError::corruption(path, offset + reader.offset as u64 - 1))?;
+        let sequence = reader.read_u64()?;
+        entries.push(Entry::with_kind(key, value, kind, sequence));
+    }
+
+    Ok(entries)
+}
+
+/// Writes a block followed by its CRC32 and returns its handle.
+fn write_block(writer: &mut impl Write, offset: &mut u64, first_key: Vec<u8>, block: &[u8]) -> Result<BlockHandle> {
+    writer.write_all(block)?;
+    writer.write_all(&crc32fast::hash(block).to_be_bytes())?;
+
+    let handle = BlockHandle { first_key, offset: *offset, size: block.len() as u32 };
+    *offset += block.len() as u64 + 4;
+    Ok(handle)
+}
+
+/// Reads a block of `size` bytes at `offset` and checks its CRC32.
+fn read_checked(file: &mut File, path: &Path, offset: u64, size: u32) -> Result<Vec<u8>> {
+    let mut data = vec![0; size as usize + 4];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut data).map_err(|_| CopperError::corruption(path, offset))?;
+
+    let crc = data.split_off(size as usize);
+    if crc32fast::hash(&data).to_be_bytes() != crc.as_slice() {
+        return Err(CopperError::corruption(path, offset));
+    }
+
+    Ok(data)
+}
+
+/// `Reader` struct reads the fields of a block of an SSTable file,
+/// reporting a `Corruption` error at the current offset in the file when the block is too short.
+struct Reader<'a> {
+    path: &'a Path,
+    data: &'a [u8],
+    offset: usize,
+    base: u64,
+}
 
-        Self { data, bloom_filter }
+impl<'a> Reader<'a> {
+    /// Reads the next `len` bytes.
+    fn read(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self.offset.checked_add(len).and_then(|end| self.data.get(self.offset..end)).ok_or_else(|| CopperError::corruption(self.path, self.base + self.offset as u64))?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    /// Reads the next big endian `u32`.
+    fn read_u32(&mut self) -> Result<u32> {
+        let bytes = self.read(4)?;
+        Ok(u32::from_be_bytes(bytes.try_into().unwrap_or_default()))
+    }
+
+    /// Reads the next big endian `u64`.
+    fn read_u64(&mut self) -> Result<u64> {
+        let bytes = self.read(8)?;
+        Ok(u64::from_be_bytes(bytes.try_into().unwrap_or_default()))
+    }
+}
+
+impl Drop for SSTable {
+    /// Deletes the file of the `SSTable` if it was marked obsolete.
+    fn drop(&mut self) {
+        if self.obsolete.load(Ordering::Acquire) {
+            let _ = fs::remove_file(&self.path);
+        }
     }
 }
 
 impl Debug for SSTable {
     /// Formats the `SSTable` for printing.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "SSTable(data: {:?})", self.data)
+        write!(f, "SSTable(path: {:?}, entries: {}, range tombstones: {}, blocks: {}, filter bits: {}, last key: {:?}, max sequence: {})", self.path, self.entry_count, self.range_tombstones.len(), self.index.len(), self.filter.as_ref().map_or(0, |filter| filter.number_of_bits()), String::from_utf8_lossy(&self.last_key), self.max_sequence)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes an `SSTable` of 100 keys, `key000` to `key099`, spread over many small data blocks.
+    fn write_sstable(dir: &Path) -> SSTable {
+        let options = Options { block_size: 64, bits_per_key: 10, ..Options::default() };
+        let entries = (0..100).map(|i| Entry::new(format!("key{:03}", i).as_bytes(), b"value", false, i + 1));
+        SSTable::create(&dir.join("sst_0_0.sst"), entries, Vec::new(), &options).unwrap()
+    }
+
+    /// Flips the first byte of a data block of an `SSTable` file.
+    fn corrupt_block(sstable: &SSTable, block: usize) {
+        let mut data = fs::read(&sstable.path).unwrap();
+        data[sstable.index[block].offset as usize] ^= 0xFF;
+        fs::write(&sstable.path, data).unwrap();
+    }
+
+    #[test]
+    fn reads_a_single_data_block_for_a_lookup() {
+        let dir = tempfile::tempdir().unwrap();
+        let sstable = write_sstable(dir.path());
+        assert!(sstable.index.len() > 10);
+
+        // Every data block but the one holding the key is corrupted, the lookup never reads them
+        let block = sstable.index.partition_point(|handle| handle.first_key.as_slice() <= b"key050".as_slice()) - 1;
+        for other in (0..sstable.index.len()).filter(|other| *other != block) {
+            corrupt_block(&sstable, other);
+        }
+        assert_eq!(sstable.get(b"key050").unwrap(), Lookup::Found { value: b"value".to_vec(), sequence: 51 });
+    }
+
+    #[test]
+    fn refuses_a_corrupted_data_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let sstable = Arc::new(write_sstable(dir.path()));
+        corrupt_block(&sstable, 1);
+
+        let first_key = sstable.index[1].first_key.clone();
+        assert!(matches!(sstable.get(&first_key), Err(CopperError::Corruption { offset, .. }) if offset == sstable.index[1].offset));
+        assert!(sstable.iter().unwrap().any(|entry| matches!(entry, Err(CopperError::Corruption { .. }))));
+        assert!(sstable.get(b"key000").is_ok());
+    }
+
+    #[test]
+    fn reloads_the_bloom_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let written = write_sstable(dir.path());
+        let sstable = SSTable::open(&written.path).unwrap();
+        assert_eq!(sstable.filter.as_ref().unwrap().bitmap(), written.filter.as_ref().unwrap().bitmap());
+
+        // Every key passes the filter, and the absent keys it rejects are answered without reading a data block
+        assert!((0..100).all(|i| sstable.may_contain(format!("key{:03}", i).as_bytes())));
+        for block in 0..sstable.index.len() {
+            corrupt_block(&sstable, block);
+        }
+        let rejected = (0..100).map(|i| format!("key{:03}x", i)).filter(|key| !sstable.may_contain(key.as_bytes())).collect::<Vec<_>>();
+        assert!(rejected.len() >= 90);
+        assert!(rejected.iter().all(|key| sstable.get(key.as_bytes()).unwrap() == Lookup::Absent));
     }
 }
@@ -0,0 +1,178 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{batch::WriteBatch, column::Column, error::Result, row::encode_row, snapshot::Snapshot};
+
+/// `Transaction` struct gathers reads and writes to apply to an `LsmTree` at once, started with `LsmTree::begin_transaction`.
+///
+/// Reads go through a `Snapshot` taken when the transaction began, along with the writes of the transaction itself,
+/// and writes are buffered until `LsmTree::commit`.
+/// The commit fails with a `Conflict` error if a key read or written by the transaction was written by someone else, or cleared, since it began,
+/// the transaction can then be retried from the start.
+#[derive(Debug)]
+pub struct Transaction {
+    snapshot: Snapshot,
+    columns: Vec<Column>,
+    batch: WriteBatch,
+    writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    reads: BTreeSet<Vec<u8>>,
+}
+
+impl Transaction {
+    /// Creates a new `Transaction`.
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshot` - The `Snapshot` of the table the transaction reads from.
+    /// * `columns` - The `Column`s of the table, to check the values written.
+    pub fn new(snapshot: Snapshot, columns: Vec<Column>) -> Self {
+        Self { snapshot, columns, batch: WriteBatch::new(), writes: BTreeMap::new(), reads: BTreeSet::new() }
+    }
+
+    /// Returns the sequence number of the last write seen by the transaction.
+    pub fn get_sequence(&self) -> u64 {
+        self.snapshot.get_sequence()
+    }
+
+    /// Retrieve the row associated with a given key, as written by the transaction or as it was when the transaction began.
+    /// The key is remembered, so the commit fails if someone else writes it meanwhile.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A byte slice that holds the key.
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        if let Some(row) = self.writes.get(key) {
+            return Ok(row.clone());
+        }
+
+        self.reads.insert(key.to_vec());
+        self.snapshot.get(key)
+    }
+
+    /// Inserts a key-value pair when the transaction commits.
+    /// Returns an `InvalidValue` error if the values do not respect the columns of the table.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A byte slice that holds the key.
+    /// * `values` - A slice of byte vectors that holds the values of the columns, in order.
+    pub fn put(&mut self, key: &[u8], values: &[Vec<u8>]) -> Result<()> {
+        let row = encode_row(&self.columns, values)?;
+        self.batch.put(key, values);
+        self.writes.insert(key.to_vec(), Some(row));
+        Ok(())
+    }
+
+    /// Deletes a key when the transaction commits.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A byte slice that holds the key.
+    pub fn delete(&mut self, key: &[u8]) {
+        self.batch.delete(key);
+        self.writes.insert(key.to_vec(), None);
+    }
+
+    /// Returns the writes of the transaction, in the order they were made.
+    pub fn get_batch(&self) -> &WriteBatch {
+        &self.batch
+    }
+
+    /// Returns the keys read or written by the transaction, the ones a commit checks for conflicts.
+    /// A key both read and written is returned twice.
+    pub fn get_keys(&self) -> impl Iterator<Item = &[u8]> {
+        self.reads.iter().chain(self.writes.keys()).map(Vec::as_slice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lsm_tree::{
+        column::{Column, DataType},
+        error::CopperError,
+        options::Options,
+        tree::LsmTree,
+    };
+
+    fn create(path: &std::path::Path) -> LsmTree {
+        LsmTree::create(path.join("table").to_string_lossy().into_owned(), vec![Column::new("Name", DataType::Text)], Options::default()).unwrap()
+    }
+
+    #[test]
+    fn conflicts_with_writes_to_its_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut table = create(dir.path());
+        table.insert(b"a", &[b"old".to_vec()]).unwrap();
+
+        // Someone else writes a key the transaction read
+        let mut transaction = table.begin_transaction();
+        assert!(transaction.get(b"a").unwrap().is_some());
+        transaction.put(b"b", &[b"new".to_vec()]).unwrap();
+        table.insert(b"a", &[b"other".to_vec()]).unwrap();
+        assert!(matches!(table.commit(transaction), Err(CopperError::Conflict(key)) if key == b"a"));
+        assert!(table.get(b"b").unwrap().is_none());
+
+        // Someone else deletes a key the transaction wrote, the write is flushed before the commit
+        let mut transaction = table.begin_transaction();
+        transaction.put(b"a", &[b"new".to_vec()]).unwrap();
+        table.delete(b"a").unwrap();
+        table.flush().unwrap();
+        let err = table.commit(transaction).unwrap_err();
+        assert!(err.is_retryable() && matches!(err, CopperError::Conflict(key) if key == b"a"));
+
+        // A range tombstone writes every key it covers
+        let mut transaction = table.begin_transaction();
+        assert!(transaction.get(b"m").unwrap().is_none());
+        transaction.put(b"n", &[b"new".to_vec()]).unwrap();
+        table.delete_range(b"l", b"o").unwrap();
+        assert!(matches!(table.commit(transaction), Err(CopperError::Conflict(key)) if key == b"m"));
+    }
+
+    #[test]
+    fn commits_beside_writes_to_other_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut table = create(dir.path());
+        table.insert(b"a", &[b"old".to_vec()]).unwrap();
+
+        let mut transaction = table.begin_transaction();
+        assert!(transaction.get(b"a").unwrap().is_some());
+        transaction.put(b"a", &[b"new".to_vec()]).unwrap();
+        transaction.delete(b"c");
+        // The transaction reads its own writes
+        assert_eq!(table.decode(&transaction.get(b"a").unwrap().unwrap()).unwrap()["Name"].get_text(), "new");
+        assert!(transaction.get(b"c").unwrap().is_none());
+
+        table.insert(b"b", &[b"other".to_vec()]).unwrap();
+        table.insert(b"c", &[b"other".to_vec()]).unwrap();
+        table.flush().unwrap();
+
+        // Only c was written by both
+        assert!(matches!(table.commit(transaction), Err(CopperError::Conflict(key)) if key == b"c"));
+
+        let mut transaction = table.begin_transaction();
+        transaction.put(b"a", &[b"new".to_vec()]).unwrap();
+        table.insert(b"b", &[b"again".to_vec()]).unwrap();
+        table.commit(transaction).unwrap();
+        assert_eq!(table.decode(&table.get(b"a").unwrap().unwrap()).unwrap()["Name"].get_text(), "new");
+    }
+
+    #[test]
+    fn conflicts_with_a_clear() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut table = create(dir.path());
+        table.insert(b"a", &[b"old".to_vec()]).unwrap();
+
+        let mut transaction = table.begin_transaction();
+        assert!(transaction.get(b"a").unwrap().is_some());
+        table.clear().unwrap();
+        transaction.put(b"a", &[b"new".to_vec()]).unwrap();
+
+        assert!(matches!(table.commit(transaction), Err(CopperError::Conflict(key)) if key == b"a"));
+        assert!(table.get(b"a").unwrap().is_none());
+
+        // A transaction begun after the clear commits
+        let mut transaction = table.begin_transaction();
+        transaction.put(b"a", &[b"new".to_vec()]).unwrap();
+        table.commit(transaction).unwrap();
+        assert!(table.get(b"a").unwrap().is_some());
+    }
+}
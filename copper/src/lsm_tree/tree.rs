@@ -1,27 +1,55 @@
 use super::{
+    background::{Background, Version},
+    batch::{BatchOperation, WriteBatch},
     column::{Column, DataType},
-    entry::Entry,
+    compaction::CompactionStats,
+    config::{self, FORMAT_VERSION},
+    cursor::Cursor,
+    entry::{self, Entry, EntryKind, Lookup},
+    error::{CopperError, Result},
+    iterator::MergeIterator,
+    lock::LockFile,
+    manifest::{self, Manifest},
     memtable::Memtable,
+    merge::{self, Merger},
+    migration,
+    options::Options,
+    row::{decode_row, encode_row},
+    snapshot::Snapshot,
     sstable::SSTable,
-    wal,
+    transaction::Transaction,
+    wal::{self, Wal, WalRecovery},
 };
 use std::{
     collections::HashMap,
     fmt::{Debug, Formatter},
-    fs,
-    io::Error,
+    ops::{Bound, RangeBounds},
+    path::Path,
+    sync::Arc,
 };
 
 use crate::lsm_tree::log::write_log;
 use crate::lsm_tree::log::Operation;
 
 /// `LsmTree` struct represents a Log-Structured Merge Tree in a database.
-/// It has a path, a `Memtable`, a vector of `Column`s, and a vector of vectors of `SSTable`s.
+/// It has a path, the `Memtable` taking the writes, shared with the current `Version` of the table, a vector of `Column`s, the open `Wal`, the sequence number of the last write,
+/// the report of the last WAL recovery, the `Options` it was opened with, the `Merger` applying their `MergeOperator`,
+/// the `Background` worker that flushes full memtables and compacts the levels of `SSTable`s,
+/// and the `LockFile` that keeps other processes from opening the table meanwhile.
+/// A table opened read-only has no `Wal` and its worker does nothing.
+/// Inside a level, the newest `SSTable` comes first.
+/// The first level holds the flushed memtables, the `CompactionStrategy` of the `Options` decides how they move down the levels.
 pub struct LsmTree {
     path: String,
-    memtable: Memtable,
+    memtable: Arc<Memtable>,
     columns: Vec<Column>,
-    levels: Vec<Vec<SSTable>>,
+    wal: Option<Wal>,
+    sequence: u64,
+    wal_recovery: WalRecovery,
+    options: Options,
+    merger: Arc<Merger>,
+    background: Background,
+    _lock: LockFile,
 }
 
 /// `Value` enum represents a value in a database entry.
@@ -33,6 +61,24 @@ pub enum Value {
     // Add more types as needed
 }
 
+impl Value {
+    /// Returns the integer held by the `Value`, or 0 if it is not an `Int`.
+    pub fn get_int(&self) -> i32 {
+        match self {
+            Value::Int(a) => *a,
+            _ => 0,
+        }
+    }
+
+    /// Returns the text held by the `Value`, or an empty string if it is not a `Text`.
+    pub fn get_text(&self) -> String {
+        match self {
+            Value::Text(a) => a.clone(),
+            _ => String::new(),
+        }
+    }
+}
+
 impl LsmTree {
     /// Creates a new `LsmTree` with the given path and columns.
     ///
@@ -40,306 +86,605 @@ impl LsmTree {
     ///
     /// * `path` - A string that specifies the path of the database.
     /// * `columns` - A vector of `Column`s that specifies the columns of the database.
+    /// * `options` - The `Options` used to open the database.
     ///
     /// The function creates the directories for the database and the SSTables,
-    /// clears the Write-Ahead Log (WAL) file and the config file,
-    /// and creates a new config file with the column names and data types.
+    /// creates a config file with the column names and data types,
+    /// and opens an empty Write-Ahead Log (WAL).
     ///
-    /// The function panics if no columns are provided.
-    pub fn new(path: String, columns: Vec<Column>) -> Self {
-        // Create the directory for the database
-        let _ = std::fs::create_dir_all(&path);
+    /// Returns an `AlreadyExists` error if there is already a table at this path,
+    /// an `AlreadyLocked` error if another process is creating it too,
+    /// and an `InvalidValue` error if no columns are provided.
+    pub fn create(path: String, columns: Vec<Column>, options: Options) -> Result<Self> {
+        if columns.is_empty() {
+            return Err(CopperError::InvalidValue("No columns provided".to_string()));
+        }
+
+        // A table exists as soon as it has a config file
+        if Path::new(&path).join("config.txt").exists() {
+            return Err(CopperError::AlreadyExists(path));
+        }
+
+        // Create the directory for the database and lock it,
+        // then drop the SSTables left by a table that was not fully deleted
+        std::fs::create_dir_all(&path)?;
+        let lock = LockFile::exclusive(path.as_ref())?;
+
+        // Another process may have created the table between the first check and the lock
+        if Path::new(&path).join("config.txt").exists() {
+            return Err(CopperError::AlreadyExists(path));
+        }
+        let _ = std::fs::remove_dir_all(format!("{}/ssts", path));
 
         // Create the directory for the SSTables
-        let _ = std::fs::create_dir_all(format!("{}/ssts", path));
-        // Clear the WAL file
-        let _ = wal::clear_wal(path.as_ref());
-        // Clear the config file
-        let _ = std::fs::remove_file(format!("{}/config.txt", path));
-
-        // Create a config file with the column names and data types
-        let config_path = format!("{}/config.txt", path);
-        let mut config = String::new();
-        if columns.is_empty() {
-            panic!("No columns provided");
-        }
-        for column in &columns {
-            config.push_str(&format!("{}|{}\n", column.get_name(), column.get_data_type().get_char()));
-        }
-        let _ = std::fs::write(config_path, config);
-
-        // Create the wal
-        let _ = fs::File::create(format!("{}/wal.txt", path));
-        write_log(path.as_ref(), Operation::Creation);
-        Self { path, memtable: Memtable::new(), columns, levels: Vec::new() }
-    }
-
-    /// Load an LSM Tree from a given path.
-    /// Reads the configuration file to get column names and data types,
-    /// loads the memtable from the Write-Ahead Log (WAL) if it exists,
-    /// and loads the SSTables from disk.
-    pub fn load(path: String) -> Result<Self, Error> {
-        // Read the config file to get the column names and data types
-        let config_path = format!("{}/config.txt", path);
-        let config = std::fs::read_to_string(config_path)?;
-        let columns: Vec<Column> = config
-            .lines()
-            .map(|line| {
-                let parts: Vec<&str> = line.split('|').collect();
-                Column::new(parts[0], DataType::from_char(parts[1].chars().next().unwrap()))
-            })
-            .collect();
-
-        // If there is already a WAL file, read the memtable from it
-        let memtable = match wal::get_memtable_from_wal(path.as_ref(), &columns) {
-            Ok(memtable) => memtable,
-            Err(wal_error) => {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    match wal_error {
-                        wal::WalError::Io(io_error) => io_error.to_string(),
-                        wal::WalError::MalformedEntry => "Malformed WAL entry".to_string(),
-                    },
-                ))
+        std::fs::create_dir_all(format!("{}/ssts", path))?;
+
+        // Create and clear the wal
+        let wal = Wal::open(path.as_ref(), options.sync_policy)?;
+        wal.clear()?;
+
+        // Create a MANIFEST without SSTables
+        let manifest = Manifest::create(path.as_ref(), &[], 0)?;
+
+        // Create a config file with the format version, the column names and data types, this makes the table exist
+        config::write_config(path.as_ref(), &columns)?;
+
+        write_log(path.as_ref(), Operation::Creation)?;
+        let merger = Arc::new(Merger::new(options.merge_operator.clone(), columns.clone()));
+        let memtable = Arc::new(Memtable::new().with_max_size(options.memtable_size));
+        let version = Version::new(Arc::clone(&memtable), Vec::new(), Vec::new());
+        let background = Background::start(path.as_ref(), options.clone(), Arc::clone(&merger), version, Vec::new(), manifest, 0);
+        Ok(Self { path, memtable, columns, wal: Some(wal), sequence: 0, wal_recovery: WalRecovery::default(), options, merger, background, _lock: lock })
+    }
+
+    /// Opens an existing `LsmTree` at the given path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A string that specifies the path of the database.
+    /// * `columns` - A vector of `Column`s that the database is expected to have.
+    /// * `options` - The `Options` used to open the database.
+    ///
+    /// Locks the table, reads the configuration file and checks that the columns match,
+    /// migrates the table if it was written with an older format,
+    /// recovers the memtable from the Write-Ahead Log (WAL) if it exists,
+    /// along with the full memtables whose logs were not flushed yet,
+    /// loads the SSTables from disk, starts the background worker,
+    /// and keeps the WAL open with the `SyncPolicy` of the given `Options`.
+    /// The outcome of the WAL recovery is available with `get_wal_recovery`.
+    ///
+    /// Returns a `NotFound` error if there is no table at this path,
+    /// an `AlreadyLocked` error if another process has it open,
+    /// and a `SchemaMismatch` error if its columns are not the expected ones.
+    pub fn open(path: String, columns: Vec<Column>, options: Options) -> Result<Self> {
+        Self::open_with(path, columns, options, false)
+    }
+
+    /// Opens an existing `LsmTree` at the given path for reading only.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A string that specifies the path of the database.
+    /// * `columns` - A vector of `Column`s that the database is expected to have.
+    /// * `options` - The `Options` used to open the database.
+    ///
+    /// Works like `open` but never writes to the table: the WAL and the logs of the full memtables are only replayed,
+    /// nothing is flushed or compacted, and every write returns a `ReadOnly` error.
+    /// Several processes can open a table read-only at the same time, as long as none has it open for writing.
+    ///
+    /// Returns a `NotFound` error if there is no table at this path,
+    /// an `AlreadyLocked` error if another process has it open for writing,
+    /// a `SchemaMismatch` error if its columns are not the expected ones,
+    /// and a `ReadOnly` error if it must first be migrated from an older format by opening it for writing.
+    pub fn open_read_only(path: String, columns: Vec<Column>, options: Options) -> Result<Self> {
+        Self::open_with(path, columns, options, true)
+    }
+
+    /// Opens an existing `LsmTree`, see `open` and `open_read_only`.
+    fn open_with(path: String, columns: Vec<Column>, options: Options, read_only: bool) -> Result<Self> {
+        if !Path::new(&path).join("config.txt").exists() {
+            return Err(CopperError::NotFound(path));
+        }
+
+        // Keep other processes from writing to the table while it is open
+        let lock = if read_only { LockFile::shared(path.as_ref())? } else { LockFile::exclusive(path.as_ref())? };
+
+        // Read the config file to get the format version, the column names and data types
+        let (format, stored_columns) = config::read_config(path.as_ref())?;
+        if stored_columns != columns {
+            return Err(CopperError::SchemaMismatch { expected: columns, found: stored_columns });
+        }
+
+        // Bring tables written by older versions to the current format
+        if format != FORMAT_VERSION {
+            if read_only {
+                return Err(CopperError::ReadOnly);
             }
-        };
+            let dropped = migration::migrate(path.as_ref(), &columns, format, &options)?;
+            write_log(path.as_ref(), Operation::Migration(format, dropped))?;
+        }
 
-        // Load the SSTables from disk, each sstable file is labeled sst_0_0, sst_0_1, sst_1_0, etc.
-        let mut levels = Vec::new();
-        // Load all levels
-        let mut i = 0;
-        loop {
-            let mut level = Vec::new();
-            let mut j = 0;
-            loop {
-                let sst_path = format!("{}/ssts/sst_{}_{}.txt", path, i, j);
-                if !std::path::Path::new(&sst_path).exists() {
-                    break;
+        // Load the SSTables listed in the MANIFEST, each sstable file is labeled sst_<level>_<id>.sst
+        let (ids, truncated) = manifest::recover(path.as_ref())?.ok_or_else(|| CopperError::corruption(Path::new(&path).join("MANIFEST"), 0))?;
+        let (levels, next_file_id) = Self::load_sstables(&path, &ids)?;
+
+        // Recover the full memtables that were waiting to be flushed from their logs, and the memtable from the WAL.
+        // Flushes happen in order, so a log whose writes are all older than the newest SSTable was flushed before a crash kept it,
+        // and the writes older than the last clear were cleared before a crash kept them
+        let flushed = levels.iter().flatten().map(|sstable| sstable.get_max_sequence()).max().unwrap_or(0).max(truncated);
+        let mut immutables = Vec::new();
+        let mut logs = Vec::new();
+        let mut wal_recovery = WalRecovery::default();
+        for (sequence, log) in wal::immutable_logs(path.as_ref())? {
+            if sequence <= flushed {
+                if !read_only {
+                    std::fs::remove_file(log)?;
                 }
+                continue;
+            }
+            let (memtable, recovery) = if read_only { wal::read_memtable_from_log(&log, truncated)? } else { wal::get_memtable_from_log(&log, truncated)? };
+            wal_recovery += recovery;
+            immutables.insert(0, Arc::new(memtable));
+            logs.insert(0, log);
+        }
+        let (memtable, recovery) = if read_only { wal::read_memtable_from_log(&Path::new(&path).join("wal.txt"), truncated)? } else { wal::get_memtable_from_wal(path.as_ref(), truncated)? };
+        let memtable = Arc::new(memtable.with_max_size(options.memtable_size));
+        wal_recovery += recovery;
+        // Continue after the newest write, which is in a log or, if it was flushed, in an SSTable
+        let sequence = flushed.max(wal_recovery.get_last_sequence());
+
+        let version = Version::new(Arc::clone(&memtable), immutables, levels);
+        let merger = Arc::new(Merger::new(options.merge_operator.clone(), columns.clone()));
+        if read_only {
+            let background = Background::idle(path.as_ref(), options.clone(), version);
+            return Ok(Self { path, memtable, columns, wal: None, sequence, wal_recovery, options, merger, background, _lock: lock });
+        }
+
+        // Drop the files left by a crash, and start a new MANIFEST holding only the current files, so it does not grow forever
+        Self::remove_orphans(&path, &ids)?;
+        let manifest = Manifest::create(path.as_ref(), &ids, truncated)?;
+
+        write_log(path.as_ref(), Operation::Recovery(wal_recovery.get_recovered(), wal_recovery.get_discarded()))?;
+        write_log(path.as_ref(), Operation::Load)?;
+        let wal = Wal::open(path.as_ref(), options.sync_policy)?;
+        let background = Background::start(path.as_ref(), options.clone(), Arc::clone(&merger), version, logs, manifest, next_file_id);
+        Ok(Self { path, memtable, columns, wal: Some(wal), sequence, wal_recovery, options, merger, background, _lock: lock })
+    }
 
-                let sstable = SSTable::load_from_disk(&sst_path)?;
-                level.push(sstable);
-                j += 1;
+    /// Opens the SSTable files of each level, given by their ids.
+    /// Returns the levels and the id to give to the next SSTable file.
+    fn load_sstables(path: &str, ids: &[Vec<u64>]) -> Result<(Vec<Vec<Arc<SSTable>>>, u64)> {
+        let mut levels = Vec::new();
+        for (level, level_ids) in ids.iter().enumerate() {
+            let mut sstables = Vec::new();
+            for id in level_ids {
+                sstables.push(Arc::new(SSTable::open(&Path::new(path).join("ssts").join(format!("sst_{}_{}.sst", level, id)))?));
             }
+            levels.push(sstables);
+        }
+
+        let next_file_id = ids.iter().flatten().map(|id| id + 1).max().unwrap_or(0);
+        Ok((levels, next_file_id))
+    }
 
-            // If no SSTables were loaded for this level, we're done
-            if level.is_empty() {
-                break;
+    /// Deletes the SSTable files that are not in the MANIFEST, given by their ids.
+    /// They were left by a flush or a compaction interrupted by a crash.
+    fn remove_orphans(path: &str, ids: &[Vec<u64>]) -> Result<()> {
+        let live: Vec<String> = ids.iter().enumerate().flat_map(|(level, level_ids)| level_ids.iter().map(move |id| format!("sst_{}_{}.sst", level, id))).collect();
+        for file in std::fs::read_dir(format!("{}/ssts", path))? {
+            let file = file?;
+            if !live.iter().any(|name| file.file_name() == name.as_str()) {
+                std::fs::remove_file(file.path())?;
             }
+        }
 
-            levels.push(level);
-            i += 1;
+        Ok(())
+    }
+
+    /// Opens the `LsmTree` at the given path, creating it if it does not exist yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A string that specifies the path of the database.
+    /// * `columns` - A vector of `Column`s that specifies the columns of the database.
+    /// * `options` - The `Options` used to open the database.
+    ///
+    /// See `create` and `open` for the errors.
+    pub fn open_or_create(path: String, columns: Vec<Column>, options: Options) -> Result<Self> {
+        if Path::new(&path).join("config.txt").exists() {
+            Self::open(path, columns, options)
+        } else {
+            Self::create(path, columns, options)
         }
-        write_log(path.as_ref(), Operation::Load);
-        Ok(Self { path, memtable, columns, levels })
     }
 
     /// Insert a key-value pair into the LSM Tree.
-    /// Checks if the values respect the columns, then inserts the key-value pair into the memtable.
-    /// If the memtable is full, it flushes it to an SSTable.
-    pub fn insert(&mut self, key: &[u8], values: &[Vec<u8>]) -> Result<(), Error> {
-        // Check that the values respects the columns
-        if values.len() != self.columns.len() {
-            return Err(Error::new(std::io::ErrorKind::InvalidInput, "Invalid number of values"));
-        }
-
-        // Flatten the values into a single byte vector with separators
-        let mut value = Vec::new();
-        for i in 0..values.len() {
-            value.extend_from_slice(&values[i]);
-            if i < values.len() - 1 {
-                value.push(b'|');
-            }
-        }
+    /// Checks if the values respect the columns and encodes them into a row,
+    /// writes the key-value pair to the WAL, then inserts it into the memtable.
+    /// If the memtable is full, it is queued to be flushed to an SSTable in the background,
+    /// which can stall the write while the background work is too far behind, see `Options`.
+    /// Returns an error without touching the memtable if the WAL write fails, and a `ReadOnly` error if the table is open read-only.
+    /// Once a WAL write fails, every later write fails until the table is opened again, see `Wal`.
+    pub fn insert(&mut self, key: &[u8], values: &[Vec<u8>]) -> Result<()> {
+        // Check that the values respects the columns and encode them
+        let value = encode_row(&self.columns, values)?;
 
-        // Insert the key-value pair into the memtable
-        self.memtable.insert(key, &value, false);
-        let _ = wal::write_to_wal(self.path.as_ref(), &Entry::new(key, &value, false));
+        // Log the write with the next sequence number, then insert the key-value pair into the memtable
+        self.wal()?.append(&Entry::new(key, &value, false, self.sequence + 1))?;
+        self.sequence += 1;
+        self.memtable.insert(key, &value, false, self.sequence, &self.background.get_snapshots());
 
-        // If the memtable is full, flush it to an SSTable
+        // If the memtable is full, queue it to be flushed to an SSTable
         if self.memtable.is_full() {
-            self.flush()?;
+            self.rotate_memtable()?;
         }
 
-        write_log(self.path.as_ref(), Operation::Insertion(key.to_vec(), value));
+        self.log(Operation::Insertion(key.to_vec(), value))?;
 
         Ok(())
     }
 
     /// Retrieve a value associated with a given key from the LSM Tree.
-    /// First checks the memtable, then each level of SSTables.
-    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
-        write_log(self.path.as_ref(), Operation::Get(key.to_vec()));
-        // Check the memtable first
-        if let Some(entry) = self.memtable.get(key) {
-            if entry.is_deleted() {
-                return Ok(None);
-            }
-
-            return Ok(Some(entry.get_value().to_vec()));
+    /// First checks the memtable, which holds the newest writes, then the full memtables waiting to be flushed, the newest first,
+    /// then the SSTables.
+    /// The newest version of the key in the SSTables is the one with the highest sequence number,
+    /// wherever its `SSTable` sits in the levels.
+    /// Returns `None` if the key is absent or if its newest version is a tombstone, or a range tombstone covering it,
+    /// older versions hidden by the tombstone are never returned.
+    /// If its newest version holds merge operands, they are folded with the older versions found by a scan of the key.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.log(Operation::Get(key.to_vec()))?;
+        match self.background.current().get(key, self.sequence)? {
+            Lookup::Found { value, .. } => Ok(Some(value)),
+            Lookup::Deleted { .. } | Lookup::Absent => Ok(None),
+            Lookup::Merge { .. } => Ok(self.scan((Bound::Included(key), Bound::Included(key)))?.next().transpose()?.map(|(_, row)| row)),
         }
+    }
 
-        // If the key is not in the memtable, check each level of SSTables
-        for level in &self.levels {
-            for sstable in level {
-                if let Some(value) = sstable.get(key) {
-                    return Ok(Some(value));
-                }
-            }
+    /// Merge an operand into the row of a key, without reading it.
+    /// The `MergeOperator` of the `Options` checks the operand, and folds it into the row when the key is read.
+    /// If the memtable holds the value or the tombstone of the key, the operand is folded into it right away,
+    /// otherwise only the operand is written to the WAL and to the memtable, above the versions of the key it is folded into.
+    /// If the memtable is full, it is queued to be flushed to an SSTable in the background,
+    /// which can stall the write while the background work is too far behind, see `Options`.
+    /// Returns a `NoMergeOperator` error if the table has no `MergeOperator`, an `InvalidValue` error if it rejects the operand,
+    /// and a `ReadOnly` error if the table is open read-only.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A byte slice that holds the key.
+    /// * `operand` - A byte slice that holds the operand, such as a 4 bytes integer for `AddInt`.
+    pub fn merge(&mut self, key: &[u8], operand: &[u8]) -> Result<()> {
+        self.wal()?;
+        self.merger.check(operand)?;
+
+        let sequence = self.sequence + 1;
+        let mut operands = Vec::new();
+        merge::append_operand(&mut operands, operand);
+        let entry = match self.memtable.get(key) {
+            // The value or the tombstone hides the older versions of the key, and so does a range tombstone
+            Lookup::Found { value, .. } => self.merger.apply(key, Some(&value), &[operand], sequence)?,
+            Lookup::Deleted { .. } => self.merger.apply(key, None, &[operand], sequence)?,
+            Lookup::Merge { .. } | Lookup::Absent => Entry::with_kind(key, &operands, EntryKind::Merge, sequence),
+        };
+
+        // Log the entry with the next sequence number, then insert it into the memtable
+        self.wal()?.append(&entry)?;
+        self.sequence = sequence;
+        self.memtable.insert_entry(entry, &self.background.get_snapshots());
+
+        // If the memtable is full, queue it to be flushed to an SSTable
+        if self.memtable.is_full() {
+            self.rotate_memtable()?;
         }
 
-        // If the key is not found, return None
-        Ok(None)
+        self.log(Operation::Merge(key.to_vec(), operand.to_vec()))?;
+
+        Ok(())
     }
 
     /// Delete a key-value pair from the LSM Tree.
-    /// Inserts a tombstone value into the memtable.
-    /// If the memtable is full, it flushes it to an SSTable.
-    pub fn delete(&mut self, key: &[u8]) -> Result<bool, Error> {
-        // Insert a tombstone value into the memtable
-        let result = self.memtable.insert(key, &[], true);
-        let mut value = Vec::new();
-        value.resize(self.columns.len() - 1, b'|');
-        let _ = wal::write_to_wal(self.path.as_ref(), &Entry::new(key, &value, true));
-
-        // If the memtable is full, flush it to an SSTable
+    /// Writes a tombstone to the WAL, then inserts it into the memtable.
+    /// If the memtable is full, it is queued to be flushed to an SSTable in the background,
+    /// which can stall the write while the background work is too far behind, see `Options`.
+    /// Returns an error without touching the memtable if the WAL write fails, and a `ReadOnly` error if the table is open read-only.
+    pub fn delete(&mut self, key: &[u8]) -> Result<bool> {
+        // Log the tombstone with the next sequence number, then insert it into the memtable
+        self.wal()?.append(&Entry::new(key, &[], true, self.sequence + 1))?;
+        self.sequence += 1;
+        let result = self.memtable.insert(key, &[], true, self.sequence, &self.background.get_snapshots());
+
+        // If the memtable is full, queue it to be flushed to an SSTable
         if self.memtable.is_full() {
-            self.flush()?;
+            self.rotate_memtable()?;
         }
 
-        write_log(self.path.as_ref(), Operation::Deletion(key.to_vec()));
+        self.log(Operation::Deletion(key.to_vec()))?;
 
         Ok(result)
     }
 
-    /// Flush the memtable to an SSTable and clear the memtable.
-    /// Creates a new SSTable and writes the contents of the memtable to it.
-    /// Adds the new SSTable to the first level.
-    /// Clears the memtable and the sst folder.
-    /// Writes all levels to disk.
-    /// Compacts the levels.
-    pub fn flush(&mut self) -> Result<(), Error> {
-        // Create a new SSTable and write the contents of the memtable to it
-        let sstable = SSTable::from_memtable(&self.memtable);
-        wal::clear_wal(self.path.as_ref()).expect("Cannot clear wal");
+    /// Delete every key of a range from the LSM Tree with a single range tombstone, without reading the keys.
+    /// Writes the range tombstone to the WAL, then adds it to the memtable.
+    /// Reads skip the versions of the keys it covers that are older than it, and compactions drop them.
+    /// If the memtable is full, it is queued to be flushed to an SSTable in the background,
+    /// which can stall the write while the background work is too far behind, see `Options`.
+    /// Nothing is written if the range is empty.
+    /// Returns an error without touching the memtable if the WAL write fails, and a `ReadOnly` error if the table is open read-only.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - A byte slice that holds the first key of the range, included.
+    /// * `end` - A byte slice that holds the end of the range, excluded.
+    pub fn delete_range(&mut self, start: &[u8], end: &[u8]) -> Result<()> {
+        self.wal()?;
+        if start >= end {
+            return Ok(());
+        }
+
+        // Log the range tombstone with the next sequence number, then add it to the memtable
+        let tombstone = Entry::with_kind(start, end, EntryKind::RangeTombstone, self.sequence + 1);
+        self.wal()?.append(&tombstone)?;
+        self.sequence += 1;
+        self.memtable.delete_range(tombstone, &self.background.get_snapshots());
 
-        // Add the new SSTable to the first level
-        if self.levels.is_empty() {
-            self.levels.push(Vec::new());
+        // If the memtable is full, queue it to be flushed to an SSTable
+        if self.memtable.is_full() {
+            self.rotate_memtable()?;
         }
-        self.levels[0].insert(0, sstable);
 
-        // Clear the memtable
-        self.memtable.clear();
+        self.log(Operation::RangeDeletion(start.to_vec(), end.to_vec()))?;
 
-        // Clear the sst folder
-        let _ = std::fs::remove_dir_all(format!("{}/ssts", self.path));
+        Ok(())
+    }
 
-        // Create the sst table folder
-        let _ = std::fs::create_dir_all(format!("{}/ssts", self.path));
+    /// Apply a `WriteBatch` to the LSM Tree.
+    /// Checks the values of every insertion and turns the writes into entries with consecutive sequence numbers,
+    /// writes them to the WAL as a single record, then inserts them into the memtable,
+    /// so after a crash either the whole batch is in the table or none of it.
+    /// A range deletion writes a range tombstone, see `delete_range`, it also deletes the keys inserted earlier in the batch.
+    /// If the memtable is full afterwards, it is queued to be flushed to an SSTable in the background, see `insert`.
+    /// Returns an error without touching the memtable if a value does not respect the columns or if the WAL write fails,
+    /// and a `ReadOnly` error if the table is open read-only.
+    pub fn write_batch(&mut self, batch: &WriteBatch) -> Result<()> {
+        self.wal()?;
 
-        // Write all levels
-        for (i, level) in self.levels.iter().enumerate() {
-            for (j, sstable) in level.iter().enumerate() {
-                let sst_path = format!("{}/ssts/sst_{}_{}.txt", self.path, i, j);
-                sstable.save_to_disk(&sst_path)?;
+        // Turn the writes into entries, numbered after the last write
+        let mut entries: Vec<Entry> = Vec::new();
+        for operation in batch.get_operations() {
+            match operation {
+                BatchOperation::Put(key, values) => {
+                    let value = encode_row(&self.columns, values)?;
+                    entries.push(Entry::new(key, &value, false, self.sequence + entries.len() as u64 + 1));
+                }
+                BatchOperation::Delete(key) => entries.push(Entry::new(key, &[], true, self.sequence + entries.len() as u64 + 1)),
+                BatchOperation::DeleteRange(start, end) if start < end => entries.push(Entry::with_kind(start, end, EntryKind::RangeTombstone, self.sequence + entries.len() as u64 + 1)),
+                BatchOperation::DeleteRange(..) => {}
             }
         }
 
-        write_log(self.path.as_ref(), Operation::Flush);
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        // Log the whole batch at once, then insert it into the memtable
+        self.wal()?.append_batch(&entries)?;
+        self.sequence += entries.len() as u64;
+        let written = entries.len();
+        let snapshots = self.background.get_snapshots();
+        for entry in entries {
+            self.memtable.insert_entry(entry, &snapshots);
+        }
+
+        // If the memtable is full, queue it to be flushed to an SSTable
+        if self.memtable.is_full() {
+            self.rotate_memtable()?;
+        }
+
+        self.log(Operation::Batch(batch.len(), written))?;
+
+        Ok(())
+    }
+
+    /// Flush the memtable to an SSTable.
+    /// Queues the memtable to be flushed in the background if it holds any write,
+    /// then waits until every queued memtable is written to an SSTable.
+    /// Returns the error of the background work if it failed, and a `ReadOnly` error if the table is open read-only.
+    pub fn flush(&mut self) -> Result<()> {
+        self.wal()?;
+        if !self.memtable.is_empty() {
+            self.rotate_memtable()?;
+        }
+
+        self.background.wait_for_flush()
+    }
+
+    /// Queues the memtable to be flushed in the background with its WAL, renamed to `wal_<sequence>.txt`
+    /// after the sequence number of its last write, and starts a new memtable with a new WAL.
+    /// Stalls first while the background work is too far behind, see `Options`.
+    fn rotate_memtable(&mut self) -> Result<()> {
+        self.background.make_room()?;
 
-        // compact the levels
-        self.compact()?;
+        let log = Path::new(&self.path).join(format!("wal_{}.txt", self.sequence));
+        std::fs::rename(Path::new(&self.path).join("wal.txt"), &log)?;
+        // Sync the rename before writing to the new WAL, a crash could otherwise undo it and lose the new WAL
+        manifest::sync_dir(self.path.as_ref())?;
+        self.wal = Some(Wal::open(self.path.as_ref(), self.options.sync_policy)?);
+        let memtable = Arc::new(Memtable::new().with_max_size(self.options.memtable_size));
+        self.background.push(Arc::clone(&memtable), log);
+        self.memtable = memtable;
 
         Ok(())
     }
 
     /// Compact the levels of the LSM Tree.
-    /// For each level, if there are more than 2 SSTables, merges them,
-    /// pushes them to the next level and removes them from the current level.
-    pub fn compact(&mut self) -> Result<(), Error> {
-        // For each level, check if there is more than 2 ss tables
-        // If so, merge them, push them to the next level and remove them from the current level
-        // Do this for each level
-
-        for i in 0..self.levels.len() {
-            // If there are more than 2 SSTables in this level
-            if self.levels[i].len() > 2 {
-                // Merge the SSTables
-                let merged = SSTable::merge(&self.levels[i]);
-
-                // If there's a next level, push the merged SSTable to it
-                if i + 1 < self.levels.len() {
-                    self.levels[i + 1].push(merged);
-                } else {
-                    // Otherwise, create a new level and push the merged SSTable to it
-                    self.levels.push(vec![merged]);
-                }
+    /// Compactions run in the background after every flush, this waits until they are done, see `wait_for_compaction`.
+    pub fn compact(&self) -> Result<()> {
+        self.wait_for_compaction()
+    }
 
-                // Remove the SSTables from the current level
-                self.levels[i].clear();
-            }
+    /// Waits until every full memtable is flushed and the `CompactionStrategy` of the `Options` picks no more compaction.
+    /// Returns the error of the background work if it failed, there is nothing to wait for if the table is open read-only.
+    pub fn wait_for_compaction(&self) -> Result<()> {
+        if self.is_read_only() {
+            return Ok(());
         }
 
-        write_log(self.path.as_ref(), Operation::Compact);
+        self.background.wait_for_compaction()
+    }
 
-        Ok(())
+    /// Returns an iterator over the live keys of the LSM Tree and their rows, in key order.
+    /// The memtables and the SSTables are merged lazily, reading one data block of each `SSTable` at a time.
+    /// The iterator goes through the SSTables of the moment it was created, whatever the background work does meanwhile.
+    /// Only the newest version of each key is returned, deleted keys are skipped.
+    pub fn iter(&self) -> Result<MergeIterator<'_>> {
+        self.scan(..)
     }
 
-    /// Get a range of entries from the LSM Tree.
-    /// Gets all entries from the memtable and the SSTables that satisfy a given predicate.
-    /// Only keeps non-deleted entries.
-    pub fn get_range<F>(&self, predicate: F) -> Result<Vec<Vec<u8>>, Error>
-    where
-        F: Fn(&Entry) -> bool,
-    {
-        let mut entries = Vec::new();
+    /// Returns an iterator over the live keys of the LSM Tree within a range and their rows, in key order.
+    ///
+    /// # Arguments
+    ///
+    /// * `range` - The range of the keys, each end can be inclusive, exclusive or unbounded.
+    ///
+    /// The memtables and every `SSTable` start directly at the lower bound, SSTables using their index,
+    /// and no data block past the upper bound is read.
+    pub fn scan<'r>(&self, range: impl RangeBounds<&'r [u8]>) -> Result<MergeIterator<'_>> {
+        let start = range.start_bound().map(|key| *key);
+        let end = range.end_bound().map(|key| *key);
+        // An inverted range holds no key
+        if entry::is_empty_range(start, end) {
+            return Ok(MergeIterator::new(Vec::new(), false));
+        }
 
-        // Get all entries from the memtable
-        for entry in self.memtable.get_all_entries() {
-            if predicate(&entry) {
-                entries.push(entry);
-            }
+        let version = self.background.current();
+        let sources = version.sources(start, end, false, self.sequence)?;
+        let range_tombstones = version.range_tombstones(self.sequence);
+
+        Ok(MergeIterator::new(sources, false).with_merger(Arc::clone(&self.merger)).with_range_tombstones(range_tombstones))
+    }
+
+    /// Returns an iterator over the live keys of the LSM Tree within a range and their rows, in reverse key order.
+    ///
+    /// # Arguments
+    ///
+    /// * `range` - The range of the keys, each end can be inclusive, exclusive or unbounded.
+    ///
+    /// The memtables and every `SSTable` start directly at the upper bound, SSTables using their index.
+    /// For example, `scan_rev(..)?.take(n)` reads the last `n` keys.
+    pub fn scan_rev<'r>(&self, range: impl RangeBounds<&'r [u8]>) -> Result<MergeIterator<'_>> {
+        let start = range.start_bound().map(|key| *key);
+        let end = range.end_bound().map(|key| *key);
+        // An inverted range holds no key
+        if entry::is_empty_range(start, end) {
+            return Ok(MergeIterator::new(Vec::new(), true));
         }
 
-        // Get all entries from the SSTables
-        for level in self.levels.iter().rev() {
-            for sstable in level.iter() {
-                for entry in sstable.get_all_entries() {
-                    if predicate(&entry) {
-                        entries.push(entry);
-                    }
-                }
+        let version = self.background.current();
+        let sources = version.sources(start, end, true, self.sequence)?;
+        let range_tombstones = version.range_tombstones(self.sequence);
+
+        Ok(MergeIterator::new(sources, true).with_merger(Arc::clone(&self.merger)).with_range_tombstones(range_tombstones))
+    }
+
+    /// Takes a `Snapshot` of the LSM Tree, a consistent view of it as it is now that ignores the writes made afterwards.
+    /// It reads the table at the sequence number of the last write, nothing is copied,
+    /// but the writes and compactions keep the versions of the keys the snapshot sees until it is dropped,
+    /// so a snapshot should not be kept longer than needed.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::new(self.background.pin(self.sequence), Arc::clone(&self.merger))
+    }
+
+    /// Begins a `Transaction` that reads from a `Snapshot` of the LSM Tree as it is now, see `commit`.
+    pub fn begin_transaction(&self) -> Transaction {
+        Transaction::new(self.snapshot(), self.columns.clone())
+    }
+
+    /// Commits a `Transaction`, applying its writes at once as a `WriteBatch`
+    /// if no key it read or wrote was written by someone else, or cleared, since it began.
+    /// Returns a `Conflict` error without writing anything otherwise, the transaction can then be retried from the start,
+    /// and a `ReadOnly` error if the table is open read-only.
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The transaction to commit.
+    pub fn commit(&mut self, transaction: Transaction) -> Result<()> {
+        self.wal()?;
+
+        // A clear wrote every key
+        let truncated = self.background.get_truncated();
+        for key in transaction.get_keys() {
+            if truncated > transaction.get_sequence() || self.last_write(key)?.is_some_and(|sequence| sequence > transaction.get_sequence()) {
+                return Err(CopperError::Conflict(key.to_vec()));
             }
         }
 
-        // Now only keep non deleted entries
-        // To do this, we  go through the entries and check if the last byte is 0 or 1, if it is 1, we remove it from the entries and will not add the other entries with the same key
+        self.write_batch(transaction.get_batch())
+    }
+
+    /// Returns the sequence number of the last write to a key, an insertion or a deletion, or `None` if it was never written.
+    fn last_write(&self, key: &[u8]) -> Result<Option<u64>> {
+        Ok(self.background.current().get(key, self.sequence)?.get_sequence())
+    }
+
+    /// Returns a `Cursor` over the live keys of the LSM Tree, not positioned yet.
+    pub fn cursor(&self) -> Cursor<'_> {
+        Cursor::new(self)
+    }
+
+    /// Returns an iterator over the live keys of the LSM Tree starting with a prefix and their rows, in key order.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - A byte slice that holds the prefix of the keys.
+    pub fn prefix_scan(&self, prefix: &[u8]) -> Result<MergeIterator<'_>> {
+        // The keys starting with the prefix are before the prefix with its last byte below 0xFF incremented
+        let mut end = prefix.to_vec();
+        while end.last() == Some(&u8::MAX) {
+            end.pop();
+        }
+        if let Some(last) = end.last_mut() {
+            *last += 1;
+            self.scan((Bound::Included(prefix), Bound::Excluded(end.as_slice())))
+        } else {
+            self.scan((Bound::Included(prefix), Bound::Unbounded))
+        }
+    }
+
+    /// Get a range of entries from the LSM Tree.
+    /// Gets the rows of all live entries from the memtable and the SSTables that satisfy a given predicate.
+    /// Only keeps the newest version of each key, by sequence number, and drops it if it is deleted.
+    /// The values are returned in key order.
+    pub fn get_range<F>(&self, predicate: F) -> Result<Vec<Vec<u8>>>
+    where
+        F: Fn(&Entry) -> bool,
+    {
         let mut result = Vec::new();
-        let mut not_to_add = Vec::new();
-        for entry in entries {
-            if entry.is_deleted() {
-                not_to_add.push(entry.get_key().to_vec());
-            } else if !not_to_add.contains(&entry.get_key().to_vec()) {
+        let mut entries = self.iter()?;
+        while let Some(entry) = entries.next_entry() {
+            let entry = entry?;
+            if predicate(&entry) {
                 result.push(entry.get_value().to_vec());
             }
         }
 
-        write_log(self.path.as_ref(), Operation::GetRange);
+        self.log(Operation::GetRange)?;
 
         Ok(result)
     }
 
     /// Get the total size of the LSM Tree.
-    /// Adds the size of the memtable and the size of each SSTable in each level.
+    /// Adds the size of the memtables and the size of each SSTable in each level.
     pub fn size(&self) -> usize {
         let mut total_size = 0;
 
-        // Add the size of the memtable
+        // Add the size of the memtables
+        let version = self.background.current();
         total_size += self.memtable.get_size();
+        total_size += version.get_immutables().iter().map(|memtable| memtable.get_size()).sum::<usize>();
 
         // Add the size of each SSTable in each level
-        for level in &self.levels {
+        for level in version.get_levels() {
             for sstable in level {
                 total_size += sstable.get_size();
             }
@@ -349,41 +694,79 @@ impl LsmTree {
     }
 
     /// Clear the LSM Tree.
-    /// Clears the memtable and each SSTable in each level.
-    pub fn clear(&mut self) -> Result<(), Error> {
-        // Clear the memtable
-        self.memtable.clear();
+    /// The clear takes the next sequence number and is recorded in the MANIFEST first,
+    /// from then on every older write is ignored when the table is loaded, wherever it is.
+    /// Then forgets the memtables and every SSTable, deletes their files, starts a new memtable and truncates the WAL,
+    /// so a crash at any point either leaves the table as it was, or cleared.
+    /// The files of the SSTables still read by a `Snapshot` or an iterator are deleted once they are dropped.
+    /// Returns a `ReadOnly` error if the table is open read-only.
+    pub fn clear(&mut self) -> Result<()> {
+        self.wal()?;
 
-        // Clear each SSTable in each level
-        for level in &mut self.levels {
-            for sstable in level {
-                sstable.clear();
-            }
-        }
+        // Record the clear, then forget the memtables and each SSTable in each level and delete their files
+        let memtable = Arc::new(Memtable::new().with_max_size(self.options.memtable_size));
+        self.background.clear(self.sequence + 1, Arc::clone(&memtable))?;
+        self.sequence += 1;
+        self.memtable = memtable;
 
-        write_log(self.path.as_ref(), Operation::Clear);
+        // Truncate the WAL
+        self.wal()?.clear()?;
+
+        self.log(Operation::Clear)?;
 
         Ok(())
     }
 
+    /// Returns true if the table was opened with `open_read_only`.
+    pub fn is_read_only(&self) -> bool {
+        self.wal.is_none()
+    }
+
+    /// Returns the open WAL, or a `ReadOnly` error if the table is open read-only.
+    fn wal(&self) -> Result<&Wal> {
+        self.wal.as_ref().ok_or(CopperError::ReadOnly)
+    }
+
+    /// Writes an operation to the log of the table, unless the table is open read-only.
+    fn log(&self, operation: Operation) -> Result<()> {
+        if self.is_read_only() {
+            return Ok(());
+        }
+
+        write_log(self.path.as_ref(), operation)
+    }
+
+    /// Returns the report of the WAL recovery done when the tree was loaded:
+    /// how many records were replayed and how many were discarded as corrupted or torn.
+    pub fn get_wal_recovery(&self) -> &WalRecovery {
+        &self.wal_recovery
+    }
+
+    /// Returns what the compactions did since the tree was opened:
+    /// how many entries they wrote and how many tombstones and older versions of keys they dropped.
+    pub fn get_compaction_stats(&self) -> CompactionStats {
+        self.background.get_compaction_stats()
+    }
+
     /// Decode a byte slice into a HashMap.
-    /// Splits the byte slice into values and decodes each value according to its data type.
-    pub fn decode(&self, data: &[u8]) -> HashMap<String, Value> {
+    /// Splits the row into values and decodes each value according to its data type.
+    /// Returns an `InvalidValue` error if the row does not match the columns.
+    pub fn decode(&self, data: &[u8]) -> Result<HashMap<String, Value>> {
         let mut map = HashMap::new();
-        let values: Vec<&[u8]> = data.split(|b| *b == b'|').collect();
-        for (i, column) in self.columns.iter().enumerate() {
+        let values = decode_row(&self.columns, data)?;
+        for (column, value) in self.columns.iter().zip(values) {
             let value = match column.get_data_type() {
-                DataType::Int => Value::Int(i32::from_ne_bytes(values[i].try_into().unwrap())),
-                DataType::Bool => Value::Bool(values[i][0] == b'\x01'),
-                DataType::Text => Value::Text(String::from_utf8(values[i].to_vec()).unwrap()),
+                DataType::Int => Value::Int(i32::from_ne_bytes(value.try_into().unwrap_or_default())),
+                DataType::Bool => Value::Bool(value[0] == b'\x01'),
+                DataType::Text => Value::Text(String::from_utf8(value).map_err(|err| CopperError::InvalidValue(err.to_string()))?),
             };
 
             map.insert(column.get_name().to_string(), value);
         }
 
-        write_log(self.path.as_ref(), Operation::Decode(data.to_vec()));
+        self.log(Operation::Decode(data.to_vec()))?;
 
-        map
+        Ok(map)
     }
 }
 
@@ -391,7 +774,7 @@ impl Debug for LsmTree {
     /// Formats the `LsmTree` for printing.
     /// Includes the path, memtable, columns, and levels in the output.
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("LsmTree").field("path", &self.path).field("memtable", &self.memtable).field("columns", &self.columns).field("levels", &self.levels).finish()
+        f.debug_struct("LsmTree").field("path", &self.path).field("sequence", &self.sequence).field("memtable", &self.memtable).field("columns", &self.columns).field("version", &self.background.current()).finish()
     }
 }
 
@@ -406,3 +789,282 @@ impl Debug for Value {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use std::sync::Arc;
+
+    use super::LsmTree;
+    use crate::lsm_tree::{
+        column::{Column, DataType},
+        compaction::LeveledCompaction,
+        error::CopperError,
+        merge::AddInt,
+        options::Options,
+        wal,
+    };
+
+    fn create(path: &std::path::Path) -> LsmTree {
+        LsmTree::create(path.join("table").to_string_lossy().into_owned(), vec![Column::new("Name", DataType::Text)], Options::default()).unwrap()
+    }
+
+    fn keys(items: impl Iterator<Item = super::Result<(Vec<u8>, Vec<u8>)>>) -> Vec<Vec<u8>> {
+        items.map(|item| item.unwrap().0).collect()
+    }
+
+    #[test]
+    fn refuses_to_create_an_existing_table() {
+        let dir = tempfile::tempdir().unwrap();
+        drop(create(dir.path()));
+
+        let path = dir.path().join("table").to_string_lossy().into_owned();
+        assert!(matches!(LsmTree::create(path.clone(), vec![Column::new("Name", DataType::Text)], Options::default()), Err(CopperError::AlreadyExists(table)) if table == path));
+    }
+
+    #[test]
+    fn refuses_to_create_a_table_without_columns() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("table").to_string_lossy().into_owned();
+        assert!(matches!(LsmTree::create(path.clone(), Vec::new(), Options::default()), Err(CopperError::InvalidValue(_))));
+        assert!(!dir.path().join("table").exists());
+    }
+
+    #[test]
+    fn refuses_to_open_a_missing_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("table").to_string_lossy().into_owned();
+        assert!(matches!(LsmTree::open(path.clone(), vec![Column::new("Name", DataType::Text)], Options::default()), Err(CopperError::NotFound(table)) if table == path));
+        assert!(matches!(LsmTree::open_read_only(path, vec![Column::new("Name", DataType::Text)], Options::default()), Err(CopperError::NotFound(_))));
+    }
+
+    #[test]
+    fn refuses_to_open_a_table_with_other_columns() {
+        let dir = tempfile::tempdir().unwrap();
+        drop(create(dir.path()));
+
+        let path = dir.path().join("table").to_string_lossy().into_owned();
+        let columns = vec![Column::new("Name", DataType::Text), Column::new("Age", DataType::Int)];
+        let result = LsmTree::open(path, columns.clone(), Options::default());
+        assert!(matches!(result, Err(CopperError::SchemaMismatch { expected, found }) if expected == columns && found == vec![Column::new("Name", DataType::Text)]));
+    }
+
+    #[test]
+    fn rejects_writes_to_a_table_open_read_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut table = create(dir.path());
+        table.insert(b"a", &[b"a".to_vec()]).unwrap();
+        drop(table);
+
+        let path = dir.path().join("table").to_string_lossy().into_owned();
+        let mut table = LsmTree::open_read_only(path.clone(), vec![Column::new("Name", DataType::Text)], Options::default()).unwrap();
+        assert!(matches!(LsmTree::open(path, vec![Column::new("Name", DataType::Text)], Options::default()), Err(CopperError::AlreadyLocked(_))));
+        assert!(matches!(table.insert(b"b", &[b"b".to_vec()]), Err(CopperError::ReadOnly)));
+        assert!(matches!(table.delete(b"a"), Err(CopperError::ReadOnly)));
+        assert!(matches!(table.delete_range(b"a", b"z"), Err(CopperError::ReadOnly)));
+        assert!(matches!(table.flush(), Err(CopperError::ReadOnly)));
+        assert!(matches!(table.clear(), Err(CopperError::ReadOnly)));
+        assert!(table.get(b"a").unwrap().is_some());
+        assert!(table.get(b"b").unwrap().is_none());
+    }
+
+    #[test]
+    fn scans_bounded_ranges() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut table = create(dir.path());
+        for key in [b"a", b"b", b"c", b"d"] {
+            table.insert(key, &[key.to_vec()]).unwrap();
+        }
+        table.flush().unwrap();
+        table.insert(b"bb", &[b"bb".to_vec()]).unwrap();
+
+        assert_eq!(keys(table.scan(b"b".as_slice()..b"d".as_slice()).unwrap()), vec![b"b".to_vec(), b"bb".to_vec(), b"c".to_vec()]);
+        assert_eq!(keys(table.scan((Bound::Excluded(b"b".as_slice()), Bound::Included(b"d".as_slice()))).unwrap()), vec![b"bb".to_vec(), b"c".to_vec(), b"d".to_vec()]);
+        assert_eq!(keys(table.scan_rev(..b"c".as_slice()).unwrap()), vec![b"bb".to_vec(), b"b".to_vec(), b"a".to_vec()]);
+        assert_eq!(keys(table.prefix_scan(b"b").unwrap()), vec![b"b".to_vec(), b"bb".to_vec()]);
+    }
+
+    #[test]
+    fn flushes_the_memtable_at_the_size_of_the_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("table").to_string_lossy().into_owned();
+        let columns = vec![Column::new("Name", DataType::Text)];
+        let options = Options { memtable_size: 1024, ..Default::default() };
+
+        let mut table = LsmTree::create(path.clone(), columns.clone(), options.clone()).unwrap();
+        for key in [b"a", b"b", b"c", b"d"] {
+            table.insert(key, &[key.to_vec()]).unwrap();
+        }
+        drop(table);
+        let mut table = LsmTree::open(path, columns, options).unwrap();
+        for key in [b"e", b"f", b"g", b"h"] {
+            table.insert(key, &[key.to_vec()]).unwrap();
+        }
+        table.wait_for_compaction().unwrap();
+        // Neither a log of a full memtable nor an SSTable was written
+        let files = std::fs::read_dir(dir.path().join("table")).unwrap().chain(std::fs::read_dir(dir.path().join("table").join("ssts")).unwrap());
+        assert!(files.map(|file| file.unwrap().file_name().to_string_lossy().into_owned()).all(|name| !name.starts_with("wal_") && !name.ends_with(".sst")));
+
+        // The default memtable is full after a few writes
+        let mut table = create(&dir.path().join("default"));
+        for key in [b"a", b"b", b"c", b"d"] {
+            table.insert(key, &[key.to_vec()]).unwrap();
+        }
+        table.wait_for_compaction().unwrap();
+        assert!(std::fs::read_dir(dir.path().join("default").join("table").join("ssts")).unwrap().count() > 0);
+    }
+
+    #[test]
+    fn scans_empty_and_inverted_ranges() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut table = create(dir.path());
+        for key in [b"a", b"b", b"z"] {
+            table.insert(key, &[key.to_vec()]).unwrap();
+        }
+        table.flush().unwrap();
+        table.insert(b"c", &[b"c".to_vec()]).unwrap();
+        let snapshot = table.snapshot();
+
+        let inverted = (Bound::Included(b"z".as_slice()), Bound::Excluded(b"a".as_slice()));
+        let empty = (Bound::Excluded(b"a".as_slice()), Bound::Excluded(b"a".as_slice()));
+        for range in [inverted, empty, (Bound::Included(b"b".as_slice()), Bound::Excluded(b"b".as_slice()))] {
+            assert_eq!(table.scan(range).unwrap().count(), 0);
+            assert_eq!(table.scan_rev(range).unwrap().count(), 0);
+            assert_eq!(snapshot.scan(range).unwrap().count(), 0);
+            assert_eq!(snapshot.scan_rev(range).unwrap().count(), 0);
+        }
+        assert_eq!(keys(table.scan(b"b".as_slice()..=b"b".as_slice()).unwrap()), vec![b"b".to_vec()]);
+    }
+
+    #[test]
+    fn purges_versions_and_tombstones_in_compactions() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = Options { compaction: Arc::new(LeveledCompaction { level0_file_limit: 1, ..Default::default() }), memtable_size: 4096, ..Default::default() };
+        let mut table = LsmTree::create(dir.path().join("table").to_string_lossy().into_owned(), vec![Column::new("Name", DataType::Text)], options).unwrap();
+        let name = |table: &LsmTree, key: &[u8]| table.get(key).unwrap().map(|row| table.decode(&row).unwrap()["Name"].get_text());
+        for key in [b"a", b"b", b"c", b"d"] {
+            table.insert(key, &[b"old".to_vec()]).unwrap();
+        }
+        table.flush().unwrap();
+
+        // The versions seen by a snapshot are kept
+        let snapshot = table.snapshot();
+        table.insert(b"a", &[b"new".to_vec()]).unwrap();
+        table.delete(b"b").unwrap();
+        table.flush().unwrap();
+        table.wait_for_compaction().unwrap();
+        assert_eq!(table.get_compaction_stats().get_versions_purged(), 0);
+        assert_eq!(table.get_compaction_stats().get_tombstones_purged(), 0);
+        assert_eq!(snapshot.get(b"b").unwrap().map(|row| table.decode(&row).unwrap()["Name"].get_text()), Some("old".to_string()));
+
+        // Once it is dropped, the older versions and the tombstone that hid them go with the next compaction
+        drop(snapshot);
+        table.insert(b"c", &[b"new".to_vec()]).unwrap();
+        table.flush().unwrap();
+        table.wait_for_compaction().unwrap();
+        let stats = table.get_compaction_stats();
+        assert_eq!((stats.get_versions_purged(), stats.get_tombstones_purged()), (3, 1));
+        // The second compaction wrote the versions and the tombstone kept for the snapshot
+        assert_eq!(stats.get_written(), 4 + 6 + 3);
+
+        assert_eq!(name(&table, b"a"), Some("new".to_string()));
+        assert_eq!(name(&table, b"b"), None);
+        assert_eq!(name(&table, b"c"), Some("new".to_string()));
+        assert_eq!(name(&table, b"d"), Some("old".to_string()));
+    }
+
+    #[test]
+    fn removes_orphan_files_on_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("table");
+        let options = Options { compaction: Arc::new(LeveledCompaction { level0_file_limit: 1, ..Default::default() }), ..Default::default() };
+        let columns = vec![Column::new("Name", DataType::Text)];
+        let files = || std::fs::read_dir(path.join("ssts")).unwrap().map(|file| file.unwrap().file_name().into_string().unwrap()).collect::<std::collections::BTreeSet<_>>();
+
+        let mut table = LsmTree::create(path.to_string_lossy().into_owned(), columns.clone(), options.clone()).unwrap();
+        table.insert(b"a", &[b"old".to_vec()]).unwrap();
+        table.flush().unwrap();
+        let flushed = files();
+        let old_files: Vec<(String, Vec<u8>)> = flushed.iter().map(|name| (name.clone(), std::fs::read(path.join("ssts").join(name)).unwrap())).collect();
+        table.insert(b"a", &[b"new".to_vec()]).unwrap();
+        table.insert(b"b", &[b"new".to_vec()]).unwrap();
+        table.flush().unwrap();
+        table.wait_for_compaction().unwrap();
+        drop(table);
+        let live = files();
+        assert!(live.is_disjoint(&flushed));
+
+        // A crash can leave the inputs of a compaction, a renamed SSTable the MANIFEST does not list yet, and a partial one
+        for (name, data) in &old_files {
+            std::fs::write(path.join("ssts").join(name), data).unwrap();
+            std::fs::write(path.join("ssts").join("sst_0_99.sst"), data).unwrap();
+        }
+        std::fs::write(path.join("ssts").join("sst_0_100.tmp"), b"partial").unwrap();
+
+        let table = LsmTree::open(path.to_string_lossy().into_owned(), columns, options).unwrap();
+        assert_eq!(files(), live);
+        assert_eq!(table.get(b"a").unwrap().map(|row| table.decode(&row).unwrap()["Name"].get_text()), Some("new".to_string()));
+        assert_eq!(keys(table.iter().unwrap()), vec![b"a".to_vec(), b"b".to_vec()]);
+    }
+
+    #[test]
+    fn cuts_range_tombstones_at_the_files_of_a_compaction() {
+        let dir = tempfile::tempdir().unwrap();
+        let compaction = LeveledCompaction { level0_file_limit: 1, target_file_size: 200, ..Default::default() };
+        let options = Options { block_size: 64, compaction: Arc::new(compaction), ..Default::default() };
+        let mut table = LsmTree::create(dir.path().join("table").to_string_lossy().into_owned(), vec![Column::new("Name", DataType::Text)], options).unwrap();
+        for i in 0..100 {
+            table.insert(format!("k{:03}", i).as_bytes(), &[b"value".to_vec()]).unwrap();
+        }
+        table.flush().unwrap();
+
+        // The snapshot keeps the range tombstone and what it deletes through the compaction
+        let snapshot = table.snapshot();
+        table.delete_range(b"k010", b"k090").unwrap();
+        table.insert(b"k050", &[b"value".to_vec()]).unwrap();
+        table.flush().unwrap();
+        table.wait_for_compaction().unwrap();
+
+        let version = table.background.current();
+        let level = &version.get_levels()[1];
+        assert!(level.len() > 1);
+        for (position, sstable) in level.iter().enumerate() {
+            for other in &level[position + 1..] {
+                assert!(sstable.get_last_key() <= other.get_first_key() || other.get_last_key() <= sstable.get_first_key());
+            }
+        }
+        for sstable in level {
+            for tombstone in sstable.get_range_tombstones() {
+                assert!(sstable.get_first_key() <= tombstone.get_key() && tombstone.get_value() <= sstable.get_last_key());
+            }
+        }
+        assert!(level.iter().filter(|sstable| !sstable.get_range_tombstones().is_empty()).count() > 1);
+
+        assert_eq!(table.iter().unwrap().count(), 21);
+        assert!(table.get(b"k050").unwrap().is_some() && table.get(b"k089").unwrap().is_none() && table.get(b"k090").unwrap().is_some());
+        assert_eq!(snapshot.iter().unwrap().count(), 100);
+    }
+
+    #[test]
+    fn logs_only_the_new_merge_operand() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("table").to_string_lossy().into_owned();
+        let columns = vec![Column::new("Name", DataType::Text), Column::new("Count", DataType::Int)];
+        let options = || Options { merge_operator: Some(Arc::new(AddInt::new("Count"))), ..Default::default() };
+        let mut table = LsmTree::create(path.clone(), columns.clone(), options()).unwrap();
+        table.insert(b"k", &[b"k".to_vec(), 10_i32.to_ne_bytes().to_vec()]).unwrap();
+        table.flush().unwrap();
+        table.merge(b"k", &1_i32.to_ne_bytes()).unwrap();
+        table.merge(b"k", &2_i32.to_ne_bytes()).unwrap();
+        drop(table);
+
+        // Each record holds one operand prefixed with its length, the memtable keeps each as a version of the key when the log is replayed
+        let records = wal::read_records(&std::path::Path::new(&path).join("wal.txt")).unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().flatten().all(|entry| entry.is_merge() && entry.get_value().len() == 8));
+
+        let table = LsmTree::open(path, columns, options()).unwrap();
+        assert_eq!(table.decode(&table.get(b"k").unwrap().unwrap()).unwrap()["Count"].get_int(), 13);
+    }
+}
@@ -1,103 +1,727 @@
 use std::{
-    fs::OpenOptions,
-    io::{BufRead, BufReader, Write},
-    path::Path,
+    fs::{self, File, OpenOptions},
+    io::{Read, Write},
+    ops::AddAssign,
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread::{self, JoinHandle},
+    time::Duration,
 };
 
-use super::{column::Column, entry::Entry, memtable::Memtable};
+use super::{
+    entry::{Entry, EntryKind},
+    error::{CopperError, Result},
+    memtable::Memtable,
+};
+
+/// Size of the header of a WAL record: the payload length and its CRC32, both big endian `u32`.
+const HEADER_SIZE: usize = 8;
+
+/// Number of bytes searched for a valid record after a record whose length is corrupted, see `continues_at`.
+const RESYNC_WINDOW: usize = 1 << 20;
+
+/// Marker of a record holding a batch of entries, in place of the byte that tells whether a single entry is deleted.
+const BATCH_MARKER: u8 = 2;
+
+/// `SyncPolicy` enum tells when the Write-Ahead Log (WAL) is flushed to stable storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Every write is synced before it is acknowledged. Concurrent writers share a single sync.
+    EveryWrite,
+    /// Writes are synced in the background at the given interval,
+    /// a crash can lose the writes of the last interval.
+    Interval(Duration),
+    /// Writes are never explicitly synced, the operating system decides when they reach the disk.
+    Never,
+}
+
+impl Default for SyncPolicy {
+    /// Syncs every write by default.
+    fn default() -> Self {
+        SyncPolicy::EveryWrite
+    }
+}
+
+/// `Wal` struct is an open handle on the Write-Ahead Log (WAL) of a table.
+/// It keeps the file open between writes and syncs it according to its `SyncPolicy`.
+/// It can be shared between threads, writers waiting for a sync are committed together by one `sync_data` call.
+/// Once a write or a sync fails, the records may or may not be in the log, so every later write fails too:
+/// a record written after them would take the sequence number of one that can come back when the log is replayed.
+pub struct Wal {
+    shared: Arc<WalShared>,
+    syncer: Option<JoinHandle<()>>,
+}
+
+/// State of a `Wal` shared with its background syncer.
+struct WalShared {
+    policy: SyncPolicy,
+    state: Mutex<WalState>,
+    changed: Condvar,
+    sync_file: File,
+}
+
+/// Mutable state of a `Wal`, records are counted so a writer knows when a sync covers its record.
+struct WalState {
+    file: File,
+    written: u64,
+    synced: u64,
+    syncs: u64,
+    syncing: bool,
+    closed: bool,
+    failed: bool,
+}
+
+/// `WalRecovery` struct reports what was found while replaying the Write-Ahead Log (WAL).
+/// It has the number of recovered and discarded records and the highest sequence number seen.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WalRecovery {
+    recovered: usize,
+    discarded: usize,
+    last_sequence: u64,
+}
+
+impl WalRecovery {
+    /// Returns the number of valid records replayed into the memtable, a batch of writes is one record.
+    pub fn get_recovered(&self) -> usize {
+        self.recovered
+    }
+
+    /// Returns the number of corrupted or torn records that were skipped.
+    pub fn get_discarded(&self) -> usize {
+        self.discarded
+    }
+
+    /// Returns the highest sequence number of the recovered records, 0 if there were none.
+    pub fn get_last_sequence(&self) -> u64 {
+        self.last_sequence
+    }
+}
+
+impl AddAssign for WalRecovery {
+    /// Adds the report of the recovery of another log.
+    fn add_assign(&mut self, other: Self) {
+        self.recovered += other.recovered;
+        self.discarded += other.discarded;
+        self.last_sequence = self.last_sequence.max(other.last_sequence);
+    }
+}
+
+impl Wal {
+    /// Opens the Write-Ahead Log (WAL) of a table, creating it if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_path` - A reference to the path of the table.
+    /// * `policy` - The `SyncPolicy` of the log.
+    ///
+    /// With `SyncPolicy::Interval`, a background thread syncs the log until the `Wal` is dropped.
+    pub fn open(table_path: &Path, policy: SyncPolicy) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(table_path.join("wal.txt"))?;
+        let sync_file = file.try_clone()?;
+
+        let state = WalState { file, written: 0, synced: 0, syncs: 0, syncing: false, closed: false, failed: false };
+        let shared = Arc::new(WalShared { policy, state: Mutex::new(state), changed: Condvar::new(), sync_file });
+
+        let syncer = match policy {
+            SyncPolicy::Interval(interval) => {
+                let shared = Arc::clone(&shared);
+                Some(thread::spawn(move || shared.sync_periodically(interval)))
+            }
+            _ => None,
+        };
+
+        Ok(Self { shared, syncer })
+    }
+
+    /// Appends an entry to the log.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - A reference to the entry to be written, stamped with the sequence number of the write.
+    ///
+    /// The entry is written as one framed record:
+    /// the payload length and the CRC32 of the payload, followed by the payload itself.
+    /// The payload holds the sequence number, the byte of the `EntryKind` of the entry,
+    /// the key prefixed with its length, and the value.
+    /// With `SyncPolicy::EveryWrite`, the function only returns once the record is synced.
+    pub fn append(&self, entry: &Entry) -> Result<()> {
+        // Build the payload
+        let mut payload = Vec::with_capacity(entry.get_size() + 12);
+        payload.extend_from_slice(&entry.get_sequence().to_be_bytes());
+        payload.push(entry.get_kind() as u8);
+        payload.extend_from_slice(&(entry.get_key().len() as u32).to_be_bytes());
+        payload.extend_from_slice(entry.get_key());
+        payload.extend_from_slice(entry.get_value());
+
+        self.write_record(&payload)
+    }
+
+    /// Appends a batch of entries to the log as a single record, so a crash keeps either all of them or none of them.
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - The entries to be written, stamped with consecutive sequence numbers.
+    ///
+    /// The payload holds the sequence number of the first entry, a batch marker and the number of entries,
+    /// then for each entry the byte of its `EntryKind`, and its key and value, both prefixed with their length.
+    /// With `SyncPolicy::EveryWrite`, the function only returns once the record is synced.
+    pub fn append_batch(&self, entries: &[Entry]) -> Result<()> {
+        let Some(first) = entries.first() else {
+            return Ok(());
+        };
+
+        // Build the payload
+        let mut payload = Vec::with_capacity(entries.iter().map(|entry| entry.get_size() + 9).sum::<usize>() + 13);
+        payload.extend_from_slice(&first.get_sequence().to_be_bytes());
+        payload.push(BATCH_MARKER);
+        payload.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+        for entry in entries {
+            payload.push(entry.get_kind() as u8);
+            payload.extend_from_slice(&(entry.get_key().len() as u32).to_be_bytes());
+            payload.extend_from_slice(entry.get_key());
+            payload.extend_from_slice(&(entry.get_value().len() as u32).to_be_bytes());
+            payload.extend_from_slice(entry.get_value());
+        }
+
+        self.write_record(&payload)
+    }
+
+    /// Frames a payload and appends it to the log, syncing it according to the `SyncPolicy`.
+    fn write_record(&self, payload: &[u8]) -> Result<()> {
+        // Frame it so the whole record is written with a single call
+        let mut record = Vec::with_capacity(HEADER_SIZE + payload.len());
+        record.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        record.extend_from_slice(&crc32fast::hash(payload).to_be_bytes());
+        record.extend_from_slice(payload);
+
+        let ticket = {
+            let mut state = self.shared.lock();
+            if state.failed {
+                return Err(failed());
+            }
+            if let Err(err) = state.file.write_all(&record) {
+                state.failed = true;
+                return Err(err.into());
+            }
+            state.written += 1;
+            state.written
+        };
+
+        if self.shared.policy == SyncPolicy::EveryWrite {
+            self.shared.sync_to(ticket)?;
+        }
+
+        Ok(())
+    }
 
-#[derive(Debug)]
-pub enum WalError {
-    Io(std::io::Error),
-    MalformedEntry,
+    /// Syncs every record appended so far, whatever the policy.
+    pub fn sync(&self) -> Result<()> {
+        let ticket = self.shared.lock().written;
+        self.shared.sync_to(ticket)
+    }
+
+    /// Clears the log.
+    ///
+    /// The file is truncated and the truncation is synced,
+    /// so cleared records cannot come back after a crash.
+    pub fn clear(&self) -> Result<()> {
+        let mut state = self.shared.lock();
+        state.file.set_len(0)?;
+        state.file.sync_data()?;
+        state.synced = state.written;
+        state.syncs += 1;
+        Ok(())
+    }
+
+    /// Returns the number of times the log was synced to stable storage.
+    pub fn get_sync_count(&self) -> u64 {
+        self.shared.lock().syncs
+    }
 }
 
-impl From<std::io::Error> for WalError {
-    fn from(err: std::io::Error) -> WalError {
-        WalError::Io(err)
+impl WalShared {
+    /// Locks the state, recovering it if another writer panicked while holding it.
+    fn lock(&self) -> MutexGuard<'_, WalState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Waits until the record numbered `ticket` is synced.
+    ///
+    /// If no sync is running, the caller becomes the leader and syncs every record written so far.
+    /// Otherwise it waits for the running sync, which may already cover its record.
+    fn sync_to(&self, ticket: u64) -> Result<()> {
+        let mut state = self.lock();
+        loop {
+            if state.failed {
+                return Err(failed());
+            }
+            if state.synced >= ticket {
+                return Ok(());
+            }
+
+            if state.syncing {
+                state = self.changed.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+                continue;
+            }
+
+            // Become the leader, sync without holding the lock so other writers can append
+            state.syncing = true;
+            let target = state.written;
+            drop(state);
+            let result = self.sync_file.sync_data();
+            state = self.lock();
+            state.syncing = false;
+            match result {
+                Ok(()) => {
+                    state.synced = state.synced.max(target);
+                    state.syncs += 1;
+                }
+                Err(_) => state.failed = true,
+            }
+            self.changed.notify_all();
+            result?;
+        }
+    }
+
+    /// Body of the background syncer of `SyncPolicy::Interval`, runs until the `Wal` is closed.
+    fn sync_periodically(&self, interval: Duration) {
+        let mut state = self.lock();
+        while !state.closed {
+            state = self.changed.wait_timeout(state, interval).unwrap_or_else(|poisoned| poisoned.into_inner()).0;
+            if state.written > state.synced {
+                let ticket = state.written;
+                drop(state);
+                let _ = self.sync_to(ticket);
+                state = self.lock();
+            }
+        }
     }
 }
 
-/// Writes an entry to the Write-Ahead Log (WAL).
+impl Drop for Wal {
+    /// Stops the background syncer and syncs the remaining records, unless the policy is `SyncPolicy::Never`.
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.changed.notify_all();
+        if let Some(syncer) = self.syncer.take() {
+            let _ = syncer.join();
+        }
+
+        if self.shared.policy != SyncPolicy::Never {
+            let _ = self.sync();
+        }
+    }
+}
+
+/// Returns the error of a write to a `Wal` after an earlier write or sync failed.
+fn failed() -> CopperError {
+    CopperError::Io(std::io::Error::other("An earlier write to the WAL failed, the table must be opened again"))
+}
+
+/// Gets a memtable from the Write-Ahead Log (WAL).
 ///
 /// # Arguments
 ///
 /// * `table_path` - A reference to the path of the table.
-/// * `entry` - A reference to the entry to be written.
+/// * `truncated` - The sequence number of the last clear of the table, older records are skipped, see `get_memtable_from_log`.
 ///
-/// The function opens the WAL file in append mode,
-/// writes the key and value of the entry to the file,
-/// writes a byte that indicates whether the entry is deleted,
-/// and writes a newline character.
-pub fn write_to_wal(table_path: &Path, entry: &Entry) -> Result<(), WalError> {
-    let wal_path = table_path.join("wal.txt");
-    let mut file = OpenOptions::new().create(true).append(true).open(wal_path)?;
+/// See `get_memtable_from_log`, a table whose WAL is missing has an empty one.
+pub fn get_memtable_from_wal(table_path: &Path, truncated: u64) -> Result<(Memtable, WalRecovery)> {
+    get_memtable_from_log(&table_path.join("wal.txt"), truncated)
+}
+
+/// Gets a memtable from a log file, the WAL of a table or the log of one of its immutable memtables.
+///
+/// # Arguments
+///
+/// * `log_path` - A reference to the path of the log.
+/// * `truncated` - The sequence number of the last clear of the table, 0 if it was never cleared.
+///
+/// The function reads the log and replays every record whose checksum is valid into the memtable, in order.
+/// Records with a bad checksum are skipped, and so are the records older than the last clear,
+/// left behind when a crash interrupted it before the log was truncated.
+/// A record cut short by a crash can only be the last one: it is discarded and the file is truncated before it,
+/// so later writes are appended after the last valid record.
+/// Returns a `Corruption` error, leaving the file as it is, if a record does not fit in the log but newer valid records follow it,
+/// as its length was corrupted and truncating the log would lose them.
+pub fn get_memtable_from_log(log_path: &Path, truncated: u64) -> Result<(Memtable, WalRecovery)> {
+    // Read the whole log file
+    let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(log_path)?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+
+    let (memtable, recovery, valid) = replay(log_path, &data, truncated)?;
 
-    // Write the entry to the WAL file
+    // Drop the torn tail
+    if valid < data.len() {
+        file.set_len(valid as u64)?;
+    }
 
-    file.write_all(format!("{}|{}|", String::from_utf8_lossy(entry.get_key()), String::from_utf8_lossy(entry.get_value())).as_bytes())?;
-    file.write_all(if entry.is_deleted() { &[b'\x01'] } else { &[b'\x00'] })?;
-    file.write_all(b"\n")?;
-    Ok(())
+    Ok((memtable, recovery))
 }
 
-/// Clears the Write-Ahead Log (WAL).
+/// Gets a memtable from a log file without writing to it, for a table opened read-only.
 ///
 /// # Arguments
 ///
-/// * `table_path` - A reference to the path of the table.
+/// * `log_path` - A reference to the path of the log.
+/// * `truncated` - The sequence number of the last clear of the table, 0 if it was never cleared.
 ///
-/// The function opens the WAL file in write mode and truncates it.
-pub fn clear_wal(table_path: &Path) -> Result<(), WalError> {
-    let wal_path = table_path.join("wal.txt");
-    // Clear the file
-    OpenOptions::new().write(true).truncate(true).open(wal_path)?;
+/// Works like `get_memtable_from_log`, but a torn last record is only skipped, and a missing log is an empty one.
+pub fn read_memtable_from_log(log_path: &Path, truncated: u64) -> Result<(Memtable, WalRecovery)> {
+    let data = match fs::read(log_path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(err.into()),
+    };
 
-    Ok(())
+    let (memtable, recovery, _) = replay(log_path, &data, truncated)?;
+    Ok((memtable, recovery))
 }
 
-/// Gets a memtable from the Write-Ahead Log (WAL).
+/// Reads the entries of each valid record of a log, in order, for a migration to rewrite the log.
+/// A missing log has none, and a torn last record is skipped, see `get_memtable_from_log`.
 ///
 /// # Arguments
 ///
-/// * `table_path` - A reference to the path of the table.
-/// * `columns` - A slice of columns.
-///
-/// The function opens the WAL file in read mode,
-/// reads each line of the file,
-/// splits each line into parts,
-/// and adds each part to the memtable.
-pub fn get_memtable_from_wal(table_path: &Path, columns: &[Column]) -> Result<Memtable, WalError> {
-    let wal_path = table_path.join("wal.txt");
-    // Open wal file
-    let file = OpenOptions::new().read(true).open(wal_path)?;
-
-    let mut memtable = Memtable::new();
-    let reader = BufReader::new(file);
-
-    for line in reader.lines() {
-        let line = line?;
-        let parts: Vec<&str> = line.split('|').collect();
-        if parts.len() != columns.len() + 2 {
-            return Err(WalError::MalformedEntry);
-        }
-
-        let key = parts[0].as_bytes().to_vec();
-        // Parse the values using the columns
-        let mut value = Vec::new();
-        for i in 0..columns.len() {
-            let value_part = parts[i + 1].as_bytes().to_vec();
-            value.extend(value_part);
-            if i < columns.len() - 1 {
-                value.push(b'|');
+/// * `log_path` - A reference to the path of the log.
+pub fn read_records(log_path: &Path) -> Result<Vec<Vec<Entry>>> {
+    let data = match fs::read(log_path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(err.into()),
+    };
+
+    let (records, _) = decode_records(log_path, &data)?;
+    Ok(records.into_iter().flatten().collect())
+}
+
+/// Replays the records of a log into a memtable, skipping the ones older than the last clear of the table.
+/// Returns the memtable, the report of the recovery and the length of the log up to the end of its last complete record,
+/// or a `Corruption` error if a record that does not fit in the log is followed by valid ones.
+fn replay(log_path: &Path, data: &[u8], truncated: u64) -> Result<(Memtable, WalRecovery, usize)> {
+    let (records, valid) = decode_records(log_path, data)?;
+    let memtable = Memtable::new();
+    let mut recovery = WalRecovery::default();
+
+    for record in records {
+        let Some(entries) = record else {
+            recovery.discarded += 1;
+            continue;
+        };
+        if entries.iter().all(|entry| entry.get_sequence() < truncated) {
+            continue;
+        }
+        recovery.recovered += 1;
+        for entry in entries {
+            recovery.last_sequence = recovery.last_sequence.max(entry.get_sequence());
+            memtable.insert_entry(entry, &[]);
+        }
+    }
+
+    // The torn tail counts as a discarded record
+    if valid < data.len() {
+        recovery.discarded += 1;
+    }
+
+    Ok((memtable, recovery, valid))
+}
+
+/// Decodes the entries of each record of a log, `None` for a record with a bad checksum.
+/// Returns them with the length of the log up to the end of its last complete record,
+/// or a `Corruption` error if a record that does not fit in the log is followed by valid ones.
+fn decode_records(log_path: &Path, data: &[u8]) -> Result<(Vec<Option<Vec<Entry>>>, usize)> {
+    let mut records = Vec::new();
+    let mut offset = 0;
+    let mut last_sequence = 0;
+
+    while offset < data.len() {
+        // A record that does not fit in the file is a torn write, which can only be the last record,
+        // so a valid record continuing the log after it means its length is corrupted
+        let Some((crc, payload)) = frame_at(data, offset) else {
+            if continues_at(data, offset, last_sequence).is_some() {
+                return Err(CopperError::corruption(log_path, offset as u64));
             }
+            break;
+        };
+        offset += HEADER_SIZE + payload.len();
+
+        let record = decode_payload(payload).filter(|_| crc32fast::hash(payload) == crc);
+        if let Some(entry) = record.as_ref().and_then(|entries| entries.last()) {
+            last_sequence = entry.get_sequence();
         }
+        records.push(record);
+    }
 
-        // Parse the last part as a boolean
-        let deleted = parts[columns.len() + 1] == "\x01";
+    Ok((records, offset))
+}
+
+/// Returns the offset of the first valid record starting in the bytes after a record whose length is corrupted, if any.
+/// Only the `RESYNC_WINDOW` bytes after the record are searched,
+/// and, as the sequence numbers of a log only grow, a record is only taken for a later one of the log if it is newer than the last valid record,
+/// so the bytes of an older record copied in a value are not.
+///
+/// # Arguments
+///
+/// * `data` - The content of the log.
+/// * `offset` - The offset of the record whose length does not fit in the log.
+/// * `last_sequence` - The sequence number of the last valid record before it, 0 if there is none.
+fn continues_at(data: &[u8], offset: usize, last_sequence: u64) -> Option<usize> {
+    let end = data.len().min(offset + HEADER_SIZE + RESYNC_WINDOW);
+    (offset + 1..end).find(|&later| {
+        let Some((crc, payload)) = frame_at(data, later) else {
+            return false;
+        };
+        let Some(sequence) = payload.get(0..8).map(|bytes| u64::from_be_bytes(bytes.try_into().unwrap_or_default())) else {
+            return false;
+        };
+        sequence > last_sequence && crc32fast::hash(payload) == crc && decode_payload(payload).is_some()
+    })
+}
 
-        memtable.insert(&key, &value, deleted);
+/// Returns the CRC32 and the payload of the record starting at an offset of a log, or `None` if it does not fit in the log.
+fn frame_at(data: &[u8], offset: usize) -> Option<(u32, &[u8])> {
+    let header = data.get(offset..offset + HEADER_SIZE)?;
+    let len = u32::from_be_bytes(header[0..4].try_into().ok()?) as usize;
+    let crc = u32::from_be_bytes(header[4..8].try_into().ok()?);
+    let payload = data.get(offset + HEADER_SIZE..offset + HEADER_SIZE + len)?;
+    Some((crc, payload))
+}
+
+/// Lists the logs of the immutable memtables of a table, which were waiting to be flushed when it was closed.
+///
+/// # Arguments
+///
+/// * `table_path` - A reference to the path of the table.
+///
+/// When a memtable is full, the WAL holding its writes is renamed to `wal_<sequence>.txt`,
+/// after the sequence number of its last write, and a new WAL is started.
+/// Returns the sequence numbers and the paths of the logs, the oldest first.
+pub fn immutable_logs(table_path: &Path) -> Result<Vec<(u64, PathBuf)>> {
+    let mut logs = Vec::new();
+    for file in fs::read_dir(table_path)? {
+        let log_path = file?.path();
+        let name = log_path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        if let Some(Ok(sequence)) = name.strip_prefix("wal_").and_then(|name| name.strip_suffix(".txt")).map(str::parse::<u64>) {
+            logs.push((sequence, log_path));
+        }
     }
 
-    Ok(memtable)
+    logs.sort();
+    Ok(logs)
+}
+
+/// Decodes the payload of a WAL record into its entry, or into the entries of a batch.
+fn decode_payload(payload: &[u8]) -> Option<Vec<Entry>> {
+    let sequence = u64::from_be_bytes(payload.get(0..8)?.try_into().ok()?);
+    if *payload.get(8)? == BATCH_MARKER {
+        return decode_batch(sequence, payload.get(9..)?);
+    }
+
+    let kind = EntryKind::from_byte(*payload.get(8)?)?;
+    let key_len = u32::from_be_bytes(payload.get(9..13)?.try_into().ok()?) as usize;
+    let key = payload.get(13..13 + key_len)?;
+    let value = payload.get(13 + key_len..)?;
+
+    Some(vec![Entry::with_kind(key, value, kind, sequence)])
+}
+
+/// Decodes the entries of a batch record, numbered from the given sequence number.
+fn decode_batch(sequence: u64, data: &[u8]) -> Option<Vec<Entry>> {
+    let count = u32::from_be_bytes(data.get(0..4)?.try_into().ok()?) as u64;
+    let mut entries = Vec::new();
+    let mut offset = 4;
+    for sequence in sequence..sequence + count {
+        let kind = EntryKind::from_byte(*data.get(offset)?)?;
+        let key_len = u32::from_be_bytes(data.get(offset + 1..offset + 5)?.try_into().ok()?) as usize;
+        let key = data.get(offset + 5..offset + 5 + key_len)?;
+        offset += 5 + key_len;
+        let value_len = u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?) as usize;
+        let value = data.get(offset + 4..offset + 4 + value_len)?;
+        offset += 4 + value_len;
+        entries.push(Entry::with_kind(key, value, kind, sequence));
+    }
+
+    Some(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsm_tree::entry::Lookup;
+
+    /// Writes a WAL holding one record per key, and returns its content and the offset of each record.
+    fn write_log(table_path: &Path, keys: &[&[u8]]) -> (Vec<u8>, Vec<usize>) {
+        let wal = Wal::open(table_path, SyncPolicy::Never).unwrap();
+        let mut offsets = Vec::new();
+        for (sequence, key) in keys.iter().enumerate() {
+            offsets.push(fs::metadata(table_path.join("wal.txt")).unwrap().len() as usize);
+            wal.append(&Entry::new(key, b"value", false, sequence as u64 + 1)).unwrap();
+        }
+        drop(wal);
+        (fs::read(table_path.join("wal.txt")).unwrap(), offsets)
+    }
+
+    #[test]
+    fn replays_every_record() {
+        let dir = tempfile::tempdir().unwrap();
+        write_log(dir.path(), &[b"a", b"b", b"c"]);
+
+        let (memtable, recovery) = get_memtable_from_wal(dir.path(), 0).unwrap();
+        assert_eq!((recovery.get_recovered(), recovery.get_discarded(), recovery.get_last_sequence()), (3, 0, 3));
+        assert_eq!(memtable.get(b"b").get_sequence(), Some(2));
+    }
+
+    #[test]
+    fn truncates_a_torn_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let (data, offsets) = write_log(dir.path(), &[b"a", b"b", b"c"]);
+
+        // The last record is cut in the middle of its payload, then in the middle of its header
+        for cut in [data.len() - 3, offsets[2] + 3] {
+            fs::write(dir.path().join("wal.txt"), &data[..cut]).unwrap();
+            let (memtable, recovery) = get_memtable_from_wal(dir.path(), 0).unwrap();
+            assert_eq!((recovery.get_recovered(), recovery.get_discarded()), (2, 1));
+            assert_eq!(memtable.get(b"c"), Lookup::Absent);
+            assert_eq!(fs::metadata(dir.path().join("wal.txt")).unwrap().len() as usize, offsets[2]);
+        }
+    }
+
+    #[test]
+    fn skips_a_record_with_a_bad_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut data, offsets) = write_log(dir.path(), &[b"a", b"b", b"c"]);
+        data[offsets[1] + HEADER_SIZE] ^= 0xFF;
+        fs::write(dir.path().join("wal.txt"), &data).unwrap();
+
+        let (memtable, recovery) = get_memtable_from_wal(dir.path(), 0).unwrap();
+        assert_eq!((recovery.get_recovered(), recovery.get_discarded()), (2, 1));
+        assert_eq!(memtable.get(b"b"), Lookup::Absent);
+        assert!(memtable.get(b"c").get_sequence().is_some());
+    }
+
+    #[test]
+    fn refuses_a_corrupted_length_before_valid_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut data, offsets) = write_log(dir.path(), &[b"a", b"b", b"c"]);
+        data[offsets[1]] = 0xFF;
+        fs::write(dir.path().join("wal.txt"), &data).unwrap();
+
+        assert!(matches!(get_memtable_from_wal(dir.path(), 0), Err(CopperError::Corruption { offset, .. }) if offset == offsets[1] as u64));
+        assert!(matches!(read_memtable_from_log(&dir.path().join("wal.txt"), 0), Err(CopperError::Corruption { .. })));
+        assert_eq!(fs::read(dir.path().join("wal.txt")).unwrap(), data);
+    }
+
+    #[test]
+    fn truncates_a_torn_tail_holding_an_older_record() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("older")).unwrap();
+        let (older, _) = write_log(&dir.path().join("older"), &[b"a"]);
+
+        // The value of the last record holds a copy of a valid record older than it
+        let wal = Wal::open(dir.path(), SyncPolicy::Never).unwrap();
+        wal.append(&Entry::new(b"a", b"value", false, 1)).unwrap();
+        wal.append(&Entry::new(b"b", &[older.as_slice(), b"padding"].concat(), false, 2)).unwrap();
+        drop(wal);
+        let data = fs::read(dir.path().join("wal.txt")).unwrap();
+        fs::write(dir.path().join("wal.txt"), &data[..data.len() - 3]).unwrap();
+
+        let (memtable, recovery) = get_memtable_from_wal(dir.path(), 0).unwrap();
+        assert_eq!((recovery.get_recovered(), recovery.get_discarded()), (1, 1));
+        assert_eq!(memtable.get(b"b"), Lookup::Absent);
+    }
+
+    #[test]
+    fn skips_the_records_older_than_a_clear() {
+        let dir = tempfile::tempdir().unwrap();
+        write_log(dir.path(), &[b"a", b"b", b"c"]);
+
+        let (memtable, recovery) = get_memtable_from_wal(dir.path(), 3).unwrap();
+        assert_eq!(recovery.get_recovered(), 1);
+        assert_eq!(memtable.get(b"a"), Lookup::Absent);
+        assert!(memtable.get(b"c").get_sequence().is_some());
+    }
+
+    #[test]
+    fn syncs_every_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let wal = Wal::open(dir.path(), SyncPolicy::EveryWrite).unwrap();
+        for sequence in 1..=3 {
+            wal.append(&Entry::new(b"a", b"value", false, sequence)).unwrap();
+            assert_eq!(wal.get_sync_count(), sequence);
+        }
+    }
+
+    #[test]
+    fn shares_a_sync_between_waiting_writers() {
+        let dir = tempfile::tempdir().unwrap();
+        let wal = Arc::new(Wal::open(dir.path(), SyncPolicy::EveryWrite).unwrap());
+
+        // The writers append while a sync is running, then wait for it
+        wal.shared.lock().syncing = true;
+        let writers: Vec<_> = (1..=4)
+            .map(|sequence| {
+                let wal = Arc::clone(&wal);
+                thread::spawn(move || wal.append(&Entry::new(&[sequence as u8], b"value", false, sequence)))
+            })
+            .collect();
+        while wal.shared.lock().written < 4 {
+            thread::yield_now();
+        }
+
+        // Once it ends, one of them syncs the records of all of them
+        wal.shared.lock().syncing = false;
+        wal.shared.changed.notify_all();
+        for writer in writers {
+            writer.join().unwrap().unwrap();
+        }
+        assert_eq!(wal.get_sync_count(), 1);
+        assert_eq!(wal.shared.lock().synced, 4);
+    }
+
+    #[test]
+    fn syncs_at_the_interval() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // Writes return before they are synced
+        let wal = Wal::open(dir.path(), SyncPolicy::Interval(Duration::from_secs(3600))).unwrap();
+        wal.append(&Entry::new(b"a", b"value", false, 1)).unwrap();
+        assert_eq!(wal.get_sync_count(), 0);
+        drop(wal);
+
+        // The background syncer syncs them
+        let wal = Wal::open(dir.path(), SyncPolicy::Interval(Duration::from_millis(10))).unwrap();
+        wal.append(&Entry::new(b"b", b"value", false, 2)).unwrap();
+        let start = std::time::Instant::now();
+        while wal.get_sync_count() == 0 {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(wal.shared.lock().synced, 1);
+    }
+
+    #[test]
+    fn never_syncs() {
+        let dir = tempfile::tempdir().unwrap();
+        let wal = Wal::open(dir.path(), SyncPolicy::Never).unwrap();
+        for sequence in 1..=3 {
+            wal.append(&Entry::new(b"a", b"value", false, sequence)).unwrap();
+        }
+        assert_eq!(wal.get_sync_count(), 0);
+
+        // Records still reach the file
+        assert_eq!(get_memtable_from_wal(dir.path(), 0).unwrap().1.get_recovered(), 3);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn fails_every_write_after_a_failed_one() {
+        let dir = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink("/dev/full", dir.path().join("wal.txt")).unwrap();
+        let wal = Wal::open(dir.path(), SyncPolicy::Never).unwrap();
+
+        assert!(matches!(wal.append(&Entry::new(b"a", b"value", false, 1)), Err(CopperError::Io(err)) if err.kind() == std::io::ErrorKind::StorageFull));
+        assert!(matches!(wal.append(&Entry::new(b"b", b"value", false, 2)), Err(CopperError::Io(err)) if err.kind() == std::io::ErrorKind::Other));
+        assert!(wal.sync().is_err());
+    }
 }
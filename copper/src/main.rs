@@ -3,6 +3,7 @@ use std::vec;
 use copper::lsm_tree::{
     self,
     column::Column,
+    options::Options,
     tree::{self, LsmTree, Value},
 };
 
@@ -45,7 +46,13 @@ fn main() {
         // Shop path
         let shop_path = format!("shops/{}", shop_name);
 
-        selected_shop = tree::LsmTree::load(shop_path).unwrap();
+        selected_shop = match tree::LsmTree::open(shop_path, shop_columns(), Options::default()) {
+            Ok(shop) => shop,
+            Err(err) => {
+                println!("Cannot open the shop: {}", err);
+                return;
+            }
+        };
         println!("Shop loaded successfully!");
     }
     println!();
@@ -136,7 +143,13 @@ fn list_books(shop: &LsmTree) {
     // Get all book with get_range returning always true for the predicate
     let books = shop.get_range(|_| true).unwrap();
     for book in books {
-        let decode = shop.decode(&book);
+        let decode = match shop.decode(&book) {
+            Ok(decode) => decode,
+            Err(err) => {
+                println!("Cannot decode a book: {}", err);
+                continue;
+            }
+        };
         let name = decode.get("Name").unwrap();
         let author = decode.get("Author").unwrap();
         let year = decode.get("Year").unwrap();
@@ -248,10 +261,16 @@ fn execute_sql_query(_shop: &mut LsmTree) {
             // Get all book with get_range returning always true for the predicate
             let books = _shop.get_range(|_| true).unwrap();
             for book in books {
-                let decode = _shop.decode(&book);
+                let decode = match _shop.decode(&book) {
+                    Ok(decode) => decode,
+                    Err(err) => {
+                        println!("Cannot decode a book: {}", err);
+                        continue;
+                    }
+                };
                 let mut row: Vec<Cell> = vec![];
                 for column in &header {
-                    let value = decode.get(&column.to_string()).unwrap();
+                    let value = decode.get(*column).unwrap();
                     match value {
                         Value::Text(value) => {
                             row.push(Cell::new(value));
@@ -301,22 +320,30 @@ fn execute_sql_query(_shop: &mut LsmTree) {
     }
 }
 
-fn create_shop() -> LsmTree {
-    println!("Enter the name of the shop:");
-    let mut shop_name = String::new();
-    std::io::stdin().read_line(&mut shop_name).unwrap();
-    let shop_name = shop_name.trim();
-
-    // Create the shop
-    let columns = vec![Column::new("Name", lsm_tree::column::DataType::Text), Column::new("Author", lsm_tree::column::DataType::Text), Column::new("Year", lsm_tree::column::DataType::Int), Column::new("In Stock", lsm_tree::column::DataType::Bool)];
+/// Columns of every shop table.
+fn shop_columns() -> Vec<Column> {
+    vec![Column::new("Name", lsm_tree::column::DataType::Text), Column::new("Author", lsm_tree::column::DataType::Text), Column::new("Year", lsm_tree::column::DataType::Int), Column::new("In Stock", lsm_tree::column::DataType::Bool)]
+}
 
-    // Shop path
-    let shop_path = format!("shops/{}", shop_name);
+fn create_shop() -> LsmTree {
+    loop {
+        println!("Enter the name of the shop:");
+        let mut shop_name = String::new();
+        std::io::stdin().read_line(&mut shop_name).unwrap();
+        let shop_name = shop_name.trim();
 
-    let shop = tree::LsmTree::new(shop_path, columns);
-    println!("Shop created successfully!");
+        // Shop path
+        let shop_path = format!("shops/{}", shop_name);
 
-    shop
+        // Create the shop
+        match tree::LsmTree::create(shop_path, shop_columns(), Options::default()) {
+            Ok(shop) => {
+                println!("Shop created successfully!");
+                return shop;
+            }
+            Err(err) => println!("Cannot create the shop: {}", err),
+        }
+    }
 }
 
 fn _test() {
@@ -327,7 +354,7 @@ fn _test() {
 
     // Create a LSM tree.
     let columns = vec![Column::new("Name", lsm_tree::column::DataType::Text), Column::new("Age", lsm_tree::column::DataType::Int)];
-    let mut lsm_tree = tree::LsmTree::new("debug_lsm_tree".to_string(), columns);
+    let mut lsm_tree = tree::LsmTree::create("debug_lsm_tree".to_string(), columns, Options::default()).unwrap();
 
     // Print the LSM tree.
     println!("{:#?}", lsm_tree);
@@ -385,6 +412,5 @@ fn _test() {
 
     // Check
     let get_result = lsm_tree.get("Jane".as_bytes());
-    let get_result = get_result;
     println!("{:?}", get_result);
 }
@@ -0,0 +1,121 @@
+use copper::lsm_tree::{
+    batch::WriteBatch,
+    column::{Column, DataType},
+    options::Options,
+    tree::LsmTree,
+};
+
+fn columns() -> Vec<Column> {
+    vec![Column::new("Name", DataType::Text)]
+}
+
+fn key(i: usize) -> Vec<u8> {
+    format!("k{:03}", i).into_bytes()
+}
+
+/// Checks the table holds exactly the given keys, forwards, backwards and one by one.
+fn assert_keys(table: &LsmTree, expected: &[usize]) {
+    let keys: Vec<Vec<u8>> = table.iter().unwrap().map(|item| item.unwrap().0).collect();
+    assert_eq!(keys, expected.iter().map(|&i| key(i)).collect::<Vec<_>>());
+    let mut keys: Vec<Vec<u8>> = table.scan_rev(..).unwrap().map(|item| item.unwrap().0).collect();
+    keys.reverse();
+    assert_eq!(keys, expected.iter().map(|&i| key(i)).collect::<Vec<_>>());
+    for i in 0..60 {
+        assert_eq!(table.get(&key(i)).unwrap().is_some(), expected.contains(&i), "key {}", i);
+    }
+}
+
+#[test]
+fn keeps_range_tombstones_across_reopen() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("table").to_string_lossy().into_owned();
+    let mut table = LsmTree::create(path.clone(), columns(), Options::default()).unwrap();
+    for i in 0..60 {
+        table.insert(&key(i), &[b"value".to_vec()]).unwrap();
+    }
+    table.flush().unwrap();
+
+    // A range tombstone in an SSTable, one in a full memtable and one in the WAL, each with a key written again after it
+    table.delete_range(&key(10), &key(20)).unwrap();
+    table.insert(&key(15), &[b"again".to_vec()]).unwrap();
+    table.flush().unwrap();
+    table.delete_range(&key(30), &key(40)).unwrap();
+    table.insert(&key(35), &[b"again".to_vec()]).unwrap();
+    let mut batch = WriteBatch::new();
+    batch.delete_range(&key(50), &key(55));
+    batch.put(&key(52), &[b"again".to_vec()]);
+    table.write_batch(&batch).unwrap();
+
+    let expected: Vec<usize> = (0..60).filter(|i| !(10..20).contains(i) && !(30..40).contains(i) && !(50..55).contains(i) || [15, 35, 52].contains(i)).collect();
+    assert_keys(&table, &expected);
+    drop(table);
+
+    let table = LsmTree::open(path.clone(), columns(), Options::default()).unwrap();
+    assert_keys(&table, &expected);
+    table.wait_for_compaction().unwrap();
+    drop(table);
+
+    let table = LsmTree::open_read_only(path.clone(), columns(), Options::default()).unwrap();
+    assert_keys(&table, &expected);
+    drop(table);
+
+    // Writes after the reopen still go over the range tombstones
+    let mut table = LsmTree::open(path, columns(), Options::default()).unwrap();
+    table.insert(&key(11), &[b"again".to_vec()]).unwrap();
+    table.flush().unwrap();
+    table.wait_for_compaction().unwrap();
+    let mut expected = expected;
+    expected.insert(expected.iter().position(|&i| i > 11).unwrap(), 11);
+    assert_keys(&table, &expected);
+}
+
+/// Copies the files of a table, the SSTables included, to another folder, skipping the given file.
+fn copy_table(from: &std::path::Path, to: &std::path::Path, skip: &str) {
+    std::fs::create_dir_all(to.join("ssts")).unwrap();
+    for folder in [from.to_path_buf(), from.join("ssts")] {
+        for file in std::fs::read_dir(&folder).unwrap() {
+            let file = file.unwrap();
+            if file.file_type().unwrap().is_file() && file.file_name() != skip {
+                std::fs::copy(file.path(), to.join(file.path().strip_prefix(from).unwrap())).unwrap();
+            }
+        }
+    }
+}
+
+#[test]
+fn keeps_a_clear_interrupted_before_its_files_are_deleted() {
+    let dir = tempfile::tempdir().unwrap();
+    let table_path = dir.path().join("table");
+    let path = table_path.to_string_lossy().into_owned();
+    let options = || Options { memtable_size: 4096, ..Default::default() };
+    let mut table = LsmTree::create(path.clone(), columns(), options()).unwrap();
+    for i in 0..4 {
+        table.insert(&key(i), &[b"value".to_vec()]).unwrap();
+    }
+    table.flush().unwrap();
+    table.delete_range(&key(0), &key(1)).unwrap();
+    table.insert(&key(4), &[b"value".to_vec()]).unwrap();
+
+    // The WAL also stands for the log of a full memtable waiting to be flushed
+    let backup = dir.path().join("backup");
+    copy_table(&table_path, &backup, "MANIFEST");
+    std::fs::copy(table_path.join("wal.txt"), backup.join("wal_6.txt")).unwrap();
+    table.clear().unwrap();
+    drop(table);
+
+    // A crash once the MANIFEST records the clear leaves every older file behind
+    copy_table(&backup, &table_path, "MANIFEST");
+    let mut table = LsmTree::open(path.clone(), columns(), options()).unwrap();
+    assert_keys(&table, &[]);
+    table.insert(&key(5), &[b"value".to_vec()]).unwrap();
+    drop(table);
+
+    let mut table = LsmTree::open(path.clone(), columns(), options()).unwrap();
+    assert_keys(&table, &[5]);
+    table.flush().unwrap();
+    table.wait_for_compaction().unwrap();
+    drop(table);
+
+    let table = LsmTree::open(path, columns(), options()).unwrap();
+    assert_keys(&table, &[5]);
+}